@@ -0,0 +1,75 @@
+/// Compare perft speed using copy-make against in place do/undo move
+use std::time::Instant;
+
+use rperft::*;
+
+const POSITIONS: [(&str, u8); 4] = [
+    (STARTING_FEN, 5),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        4,
+    ),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 6),
+    (
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        5,
+    ),
+];
+
+fn perft_copy_make(pos: &Position, depth: u8) -> u64 {
+    if depth == 1 {
+        let mut count = MoveCounter::default();
+        generate_all(pos, &mut count);
+        return count.nodes;
+    }
+    let mut moves = MoveVec::new();
+    generate_all(pos, &mut moves);
+    moves
+        .iter()
+        .map(|mv| perft_copy_make(&pos.make_move(mv), depth - 1))
+        .sum()
+}
+
+fn perft_do_undo(pos: &mut Position, depth: u8) -> u64 {
+    if depth == 1 {
+        let mut count = MoveCounter::default();
+        generate_all(pos, &mut count);
+        return count.nodes;
+    }
+    let mut moves = MoveVec::new();
+    generate_all(pos, &mut moves);
+    let mut nodes = 0;
+    for mv in moves.iter() {
+        let undo = pos.do_move(mv);
+        nodes += perft_do_undo(pos, depth - 1);
+        pos.undo_move(mv, undo);
+    }
+    nodes
+}
+
+fn main() {
+    initialize();
+    println!(
+        "{:<10} {:>12} {:>12} {:>12}",
+        "position", "nodes", "copy Mn/s", "undo Mn/s"
+    );
+    for (i, (fen, depth)) in POSITIONS.iter().enumerate() {
+        let mut pos = Position::from_fen(fen).expect("valid fen");
+
+        let start = Instant::now();
+        let nodes = perft_copy_make(&pos, *depth);
+        let copy_rate = nodes as f64 / start.elapsed().as_secs_f64() / 1e6;
+
+        let start = Instant::now();
+        assert_eq!(perft_do_undo(&mut pos, *depth), nodes);
+        let undo_rate = nodes as f64 / start.elapsed().as_secs_f64() / 1e6;
+
+        println!(
+            "{:<10} {:>12} {:>12.1} {:>12.1}",
+            i + 1,
+            nodes,
+            copy_rate,
+            undo_rate
+        );
+    }
+}
@@ -0,0 +1,88 @@
+/// Compare perft speed making every leaf parent's children with full
+/// copy-make against the perft search, which skips the key updates of the
+/// children of leaf parents
+use std::time::Instant;
+
+use rperft::*;
+
+/// The standard suite run by `--bench`
+const POSITIONS: [(&str, u8); 6] = [
+    (STARTING_FEN, 6),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        5,
+    ),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 7),
+    (
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        5,
+    ),
+    (
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        5,
+    ),
+    (
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        5,
+    ),
+];
+
+fn perft_copy_make(pos: &Position, depth: u8) -> u64 {
+    if depth == 1 {
+        let mut count = MoveCounter::default();
+        generate_all(pos, &mut count);
+        return count.nodes;
+    }
+    let mut moves = MoveVec::new();
+    generate_all(pos, &mut moves);
+    moves
+        .iter()
+        .map(|mv| perft_copy_make(&pos.make_move(mv), depth - 1))
+        .sum()
+}
+
+/// Perft as run by the CLI, without threads or cache, which makes the
+/// children of leaf parents without updating their keys
+fn perft_unhashed(fen: &str, depth: u8) -> u64 {
+    let threads = perft::ThreadOptions {
+        multithreading: false,
+        ..Default::default()
+    };
+    let cache = perft::CacheOptions {
+        size: 0,
+        ..Default::default()
+    };
+    perft::perft_divide(fen, depth, &cache, &threads, false, Backend::Legal)
+        .expect("valid fen")
+        .iter()
+        .map(|(_, count)| count.nodes)
+        .sum()
+}
+
+fn main() {
+    initialize();
+    println!(
+        "{:<10} {:>12} {:>12} {:>14} {:>8}",
+        "position", "nodes", "copy Mn/s", "unhashed Mn/s", "speedup"
+    );
+    for (i, (fen, depth)) in POSITIONS.iter().enumerate() {
+        let pos = Position::from_fen(fen).expect("valid fen");
+
+        let start = Instant::now();
+        let nodes = perft_copy_make(&pos, *depth);
+        let copy_rate = nodes as f64 / start.elapsed().as_secs_f64() / 1e6;
+
+        let start = Instant::now();
+        assert_eq!(perft_unhashed(fen, *depth), nodes);
+        let unhashed_rate = nodes as f64 / start.elapsed().as_secs_f64() / 1e6;
+
+        println!(
+            "{:<10} {:>12} {:>12.1} {:>14.1} {:>8.2}",
+            i + 1,
+            nodes,
+            copy_rate,
+            unhashed_rate,
+            unhashed_rate / copy_rate
+        );
+    }
+}
@@ -1,6 +1,10 @@
+#[cfg(target_arch = "aarch64")]
 use std::arch::is_aarch64_feature_detected;
 
 fn main() {
+    for cfg in ["USE_PEXT", "USE_AUTO", "USE_NEON"] {
+        println!("cargo:rustc-check-cfg=cfg({cfg})");
+    }
     // Use PEXT instruction, instead of magic hashing on x86_64 architectures
     #[cfg(target_arch = "x86_64")]
     {
@@ -9,6 +13,7 @@ fn main() {
         }
     }
     // Determine SIMD instruction set to use for NNUE, use LLVM auto-vectorization as a fallback
+    #[allow(unused_mut)]
     let mut simd = "AUTO";
     #[cfg(target_arch = "aarch64")]
     {
@@ -507,22 +507,19 @@ impl BitBoard {
     }
 
     /// Convert from algebraic notation e.g. a5 to a one bit bitboard
-    pub fn from_algebraic(algebraic: &str) -> Result<BitBoard, ()> {
+    pub fn from_algebraic(algebraic: &str) -> Option<BitBoard> {
         let chars: Vec<char> = algebraic.chars().collect();
         if chars.len() != 2 {
-            return Err(());
-        }
-        if !chars[0].is_alphabetic() || !chars[1].is_numeric() {
-            return Err(());
+            return None;
         }
-        let file = chars[0].to_ascii_lowercase() as u8 - ascii::LOWER_A as u8;
-        let rank = chars[1] as u8 - ascii::ZERO as u8;
-        if rank <= 8 && file <= 8 {
-            let square_index = file + (rank - 1) * 8;
-            Ok(BitBoard(1 << square_index))
-        } else {
-            Err(())
+        let file = chars[0].to_ascii_lowercase();
+        let rank = chars[1];
+        if !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+            return None;
         }
+        let file_index = file as usize - ascii::LOWER_A;
+        let rank_index = rank as usize - ascii::ZERO - 1;
+        Some(BitBoard::from_sq(file_index + rank_index * 8))
     }
 
     /// Convert a one bit bitboard into algebraic notation
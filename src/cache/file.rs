@@ -0,0 +1,336 @@
+/// Memory mapped cache files, which keep their entries between runs
+use super::*;
+
+use std::fs::OpenOptions;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use memmap2::MmapMut;
+
+const MAGIC: [u8; 8] = *b"RPERFTTC";
+const VERSION: u32 = 3;
+
+/// Entries start after the header, keeping them aligned to cache lines
+const HEADER_SIZE: usize = 64;
+
+/// Error returned when a cache file cannot be used
+#[derive(Debug)]
+pub enum CacheFileError {
+    Io(std::io::Error),
+    /// The file is not a cache file, or its size does not match its header
+    Corrupt(String),
+    /// The file was written by a different version of the format
+    Version(u32),
+    /// The file stores a different kind of entry, e.g. detailed counts
+    EntryKind {
+        expected: u32,
+        found: u32,
+    },
+    /// The file was keyed with different Zobrist keys
+    HashScheme,
+    /// The file was not closed cleanly, so entries may be partly written
+    Dirty,
+    /// The entries do not match the checksum written when the file was closed
+    Checksum,
+}
+
+impl std::fmt::Display for CacheFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheFileError::Io(e) => write!(f, "{e}"),
+            CacheFileError::Corrupt(reason) => write!(f, "not a valid cache file: {reason}"),
+            CacheFileError::Version(v) => {
+                write!(f, "cache file has format version {v}, expected {VERSION}")
+            }
+            CacheFileError::EntryKind { expected, found } => write!(
+                f,
+                "cache file stores {found} word entries, expected {expected} word entries"
+            ),
+            CacheFileError::HashScheme => write!(f, "cache file uses different hash keys"),
+            CacheFileError::Dirty => write!(f, "cache file was not closed cleanly"),
+            CacheFileError::Checksum => write!(f, "cache file entries fail the checksum"),
+        }
+    }
+}
+
+impl std::error::Error for CacheFileError {}
+
+impl From<std::io::Error> for CacheFileError {
+    fn from(e: std::io::Error) -> Self {
+        CacheFileError::Io(e)
+    }
+}
+
+/// The fields recorded at the start of a cache file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    version: u32,
+    entry_kind: u32,
+    entry_size: u32,
+    dirty: u32,
+    n_entries: u64,
+    hash_scheme: u64,
+    checksum: u64,
+}
+
+impl Header {
+    fn to_bytes(self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[0..8].copy_from_slice(&MAGIC);
+        bytes[8..12].copy_from_slice(&self.version.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.entry_kind.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.entry_size.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.dirty.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.n_entries.to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.hash_scheme.to_le_bytes());
+        bytes[40..48].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, CacheFileError> {
+        if bytes.len() < HEADER_SIZE || bytes[0..8] != MAGIC {
+            return Err(CacheFileError::Corrupt("missing header".to_string()));
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().expect("4 bytes"));
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().expect("8 bytes"));
+        Ok(Self {
+            version: u32_at(8),
+            entry_kind: u32_at(12),
+            entry_size: u32_at(16),
+            dirty: u32_at(20),
+            n_entries: u64_at(24),
+            hash_scheme: u64_at(32),
+            checksum: u64_at(40),
+        })
+    }
+}
+
+/// A fingerprint of the Zobrist keys, so files written with other keys are
+/// rejected
+fn hash_scheme() -> u64 {
+    position::Position::new_start_pos().key
+}
+
+/// Entries stored in a memory mapped file. The header is marked dirty while
+/// the file is open, and the checksum is written when it is dropped
+pub struct MappedEntries<T: SizedEntry> {
+    mmap: MmapMut,
+    header: Header,
+    _entry: PhantomData<T>,
+}
+
+impl<T: SizedEntry> MappedEntries<T> {
+    /// Open the cache file at the path, creating it with room for
+    /// `size_bytes` of entries if it does not exist. An existing file keeps
+    /// its own size, and is rejected if it is incompatible or corrupt
+    pub fn open(path: &Path, size_bytes: usize) -> Result<Self, CacheFileError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let expected = Header {
+            version: VERSION,
+            entry_kind: T::KIND,
+            entry_size: size_of::<T>() as u32,
+            dirty: 0,
+            n_entries: Cache::<T>::capacity(size_bytes) as u64,
+            hash_scheme: hash_scheme(),
+            checksum: 0,
+        };
+
+        let len = file.metadata()?.len();
+        let created = len == 0;
+        if created {
+            file.set_len(HEADER_SIZE as u64 + expected.n_entries * size_of::<T>() as u64)?;
+        }
+
+        // Safety: the file is only modified through this mapping while open
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+        let header = if created {
+            expected
+        } else {
+            let header = Header::from_bytes(&mmap)?;
+            validate(&header, &expected, len)?;
+            if checksum(&mmap[HEADER_SIZE..]) != header.checksum {
+                return Err(CacheFileError::Checksum);
+            }
+            header
+        };
+
+        // Mark the file dirty until it is closed
+        let dirty = Header { dirty: 1, ..header };
+        mmap[..HEADER_SIZE].copy_from_slice(&dirty.to_bytes());
+        mmap.flush_range(0, HEADER_SIZE)?;
+
+        Ok(Self {
+            mmap,
+            header,
+            _entry: PhantomData,
+        })
+    }
+
+    pub fn entries(&self) -> &[T] {
+        // Safety: the mapping is page aligned and the header keeps entries
+        // aligned. Entries consist of atomic words, valid for any bit pattern
+        unsafe {
+            std::slice::from_raw_parts(
+                self.mmap.as_ptr().add(HEADER_SIZE) as *const T,
+                self.header.n_entries as usize,
+            )
+        }
+    }
+}
+
+impl<T: SizedEntry> Drop for MappedEntries<T> {
+    fn drop(&mut self) {
+        let header = Header {
+            dirty: 0,
+            checksum: checksum(&self.mmap[HEADER_SIZE..]),
+            ..self.header
+        };
+        self.mmap[..HEADER_SIZE].copy_from_slice(&header.to_bytes());
+        if let Err(e) = self.mmap.flush() {
+            log::error!("Could not write cache file: {e}");
+        }
+    }
+}
+
+/// Check the header of an existing file against the expected entry type
+fn validate(header: &Header, expected: &Header, len: u64) -> Result<(), CacheFileError> {
+    if header.version != expected.version {
+        return Err(CacheFileError::Version(header.version));
+    }
+    if header.entry_kind != expected.entry_kind || header.entry_size != expected.entry_size {
+        return Err(CacheFileError::EntryKind {
+            expected: expected.entry_kind,
+            found: header.entry_kind,
+        });
+    }
+    if header.hash_scheme != expected.hash_scheme {
+        return Err(CacheFileError::HashScheme);
+    }
+    if header.dirty != 0 {
+        return Err(CacheFileError::Dirty);
+    }
+    let expected_len = HEADER_SIZE as u64 + header.n_entries * header.entry_size as u64;
+    let n_buckets = header.n_entries / BUCKET_SIZE as u64;
+    if n_buckets * BUCKET_SIZE as u64 != header.n_entries
+        || !n_buckets.is_power_of_two()
+        || len != expected_len
+    {
+        return Err(CacheFileError::Corrupt(format!(
+            "{len} bytes, expected {expected_len}"
+        )));
+    }
+    Ok(())
+}
+
+/// Checksum the entry data one word at a time
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes
+        .chunks_exact(8)
+        .map(|word| u64::from_le_bytes(word.try_into().expect("8 bytes")))
+        .fold(0xcbf29ce484222325, |hash, word| {
+            (hash.rotate_left(5) ^ word).wrapping_mul(0x100000001b3)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A cache file in the temporary directory, removed when dropped
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("rperft-{}-{name}", std::process::id()));
+            let _ = fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn count(nodes: u64) -> MoveCounter {
+        MoveCounter {
+            nodes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_entries_persist() {
+        let file = TempFile::new("persist");
+        {
+            let cache = Cache::<Entry2xU64>::open(&file.0, 1024, Replacement::Depth).unwrap();
+            assert_eq!(cache.n_buckets, 16);
+            cache.write(0x1234, 3, &count(8902));
+        }
+        // The size of an existing file is kept
+        let cache = Cache::<Entry2xU64>::open(&file.0, 4096, Replacement::Depth).unwrap();
+        assert_eq!(cache.n_buckets, 16);
+        assert!(matches!(cache.read(0x1234, 3), Access::Hit(c) if c.nodes == 8902));
+    }
+
+    #[test]
+    fn test_reject_entry_kind() {
+        let file = TempFile::new("kind");
+        drop(Cache::<Entry2xU64>::open(&file.0, 1024, Replacement::Depth).unwrap());
+        assert!(matches!(
+            Cache::<Entry6xU64>::open(&file.0, 1024, Replacement::Depth),
+            Err(CacheFileError::EntryKind {
+                expected: 6,
+                found: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_reject_dirty() {
+        let file = TempFile::new("dirty");
+        let cache = Cache::<Entry2xU64>::open(&file.0, 1024, Replacement::Depth).unwrap();
+        // Opening the file while it is in use must fail
+        assert!(matches!(
+            Cache::<Entry2xU64>::open(&file.0, 1024, Replacement::Depth),
+            Err(CacheFileError::Dirty)
+        ));
+        drop(cache);
+        assert!(Cache::<Entry2xU64>::open(&file.0, 1024, Replacement::Depth).is_ok());
+    }
+
+    #[test]
+    fn test_reject_corrupt() {
+        let file = TempFile::new("corrupt");
+        drop(Cache::<Entry2xU64>::open(&file.0, 1024, Replacement::Depth).unwrap());
+
+        let mut bytes = fs::read(&file.0).unwrap();
+        bytes[HEADER_SIZE + 100] ^= 1;
+        fs::write(&file.0, &bytes).unwrap();
+        assert!(matches!(
+            Cache::<Entry2xU64>::open(&file.0, 1024, Replacement::Depth),
+            Err(CacheFileError::Checksum)
+        ));
+
+        fs::write(&file.0, &bytes[..HEADER_SIZE + 512]).unwrap();
+        assert!(matches!(
+            Cache::<Entry2xU64>::open(&file.0, 1024, Replacement::Depth),
+            Err(CacheFileError::Corrupt(_))
+        ));
+
+        fs::write(&file.0, b"not a cache file").unwrap();
+        assert!(matches!(
+            Cache::<Entry2xU64>::open(&file.0, 1024, Replacement::Depth),
+            Err(CacheFileError::Corrupt(_))
+        ));
+    }
+}
@@ -0,0 +1,580 @@
+/// Caching for transposition
+use super::*;
+
+use std::mem::size_of;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use movelist::*;
+
+mod file;
+#[cfg(target_os = "linux")]
+mod pages;
+
+pub use file::CacheFileError;
+use file::MappedEntries;
+#[cfg(target_os = "linux")]
+use pages::{PageMemory, HUGE_PAGE_SIZE};
+
+/// Number of entries inspected to estimate how full the cache is
+const FILL_SAMPLE_SIZE: usize = 1 << 16;
+
+/// Number of entries sharing an index. Four two word entries fill a 64 byte
+/// cache line
+pub const BUCKET_SIZE: usize = 4;
+
+pub enum Access {
+    Hit(MoveCounter),
+    Miss,
+    Collision,
+    /// The key matched an entry which fails its checksum
+    Torn,
+}
+
+/// Which entry of a full bucket is replaced by a new entry
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Replacement {
+    /// The entry selected by the key, like a cache without buckets
+    Always,
+    /// The entry with the lowest depth
+    #[default]
+    Depth,
+    /// The entry with the lowest node count
+    Nodes,
+    /// Half the bucket keeps the deepest entries, the other half is always
+    /// replaced
+    TwoTier,
+}
+
+/// A group of entries sharing an index, aligned to cache lines
+#[repr(C, align(64))]
+#[derive(Clone, Default)]
+struct Bucket<T: SizedEntry>([T; BUCKET_SIZE]);
+
+pub struct Cache<T: SizedEntry> {
+    entries: Storage<T>,
+    n_buckets: usize,
+    replacement: Replacement,
+}
+
+/// Where the entries of a cache live
+enum Storage<T: SizedEntry> {
+    Memory(Box<[Bucket<T>]>),
+    /// Large caches are mapped on huge pages where possible
+    #[cfg(target_os = "linux")]
+    Pages(PageMemory<T>),
+    File(MappedEntries<T>),
+}
+
+impl<T: SizedEntry> Cache<T> {
+    /// Initialize cache, zeroing the entries of a large cache with the
+    /// given number of threads
+    pub fn new(size_bytes: usize, replacement: Replacement, threads: usize) -> Self {
+        let n_buckets = Self::buckets_for(size_bytes);
+        Self {
+            entries: Self::allocate(n_buckets, threads),
+            n_buckets,
+            replacement,
+        }
+    }
+
+    /// The number of buckets in a cache of at most `size_bytes`, a power of
+    /// two so that keys are indexed with a mask. At least one bucket is used
+    pub fn buckets_for(size_bytes: usize) -> usize {
+        let n_buckets = (size_bytes / size_of::<Bucket<T>>()).max(1);
+        1 << n_buckets.ilog2()
+    }
+
+    /// The number of entries in a cache of at most `size_bytes`
+    pub fn capacity(size_bytes: usize) -> usize {
+        Self::buckets_for(size_bytes) * BUCKET_SIZE
+    }
+
+    /// The bytes allocated for a cache of at most `size_bytes`, including
+    /// any padding of the buckets
+    pub fn allocated_size(size_bytes: usize) -> usize {
+        Self::buckets_for(size_bytes) * size_of::<Bucket<T>>()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn allocate(n_buckets: usize, threads: usize) -> Storage<T> {
+        if n_buckets * size_of::<Bucket<T>>() >= HUGE_PAGE_SIZE {
+            match PageMemory::allocate(n_buckets, threads) {
+                Some(memory) => return Storage::Pages(memory),
+                None => log::warn!("Could not map cache memory, falling back to the heap"),
+            }
+        }
+        Storage::Memory(vec![Bucket::default(); n_buckets].into())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn allocate(n_buckets: usize, _threads: usize) -> Storage<T> {
+        Storage::Memory(vec![Bucket::default(); n_buckets].into())
+    }
+
+    /// Open a cache backed by a memory mapped file, so that entries are kept
+    /// between runs. A new file is created with room for `size_bytes` of
+    /// entries, while an existing file keeps its size
+    pub fn open(
+        path: &Path,
+        size_bytes: usize,
+        replacement: Replacement,
+    ) -> Result<Self, CacheFileError> {
+        let mapped = MappedEntries::open(path, size_bytes)?;
+        let n_buckets = mapped.entries().len() / BUCKET_SIZE;
+        Ok(Self {
+            entries: Storage::File(mapped),
+            n_buckets,
+            replacement,
+        })
+    }
+
+    /// The entries of the bucket at an index. Buckets in memory may be
+    /// padded to a multiple of the cache line, so they are indexed as
+    /// buckets, while a cache file stores the entries without padding
+    #[inline(always)]
+    fn bucket_at(&self, index: usize) -> &[T] {
+        debug_assert!(index < self.n_buckets);
+        match &self.entries {
+            // Safety: the index is below the number of buckets
+            Storage::Memory(buckets) => unsafe { &buckets.get_unchecked(index).0 },
+            #[cfg(target_os = "linux")]
+            Storage::Pages(memory) => unsafe { &memory.buckets().get_unchecked(index).0 },
+            Storage::File(mapped) => {
+                let start = index * BUCKET_SIZE;
+                unsafe { mapped.entries().get_unchecked(start..start + BUCKET_SIZE) }
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn bucket(&self, key: u64) -> &[T] {
+        // The number of buckets is a power of two
+        self.bucket_at(key as usize & (self.n_buckets - 1))
+    }
+
+    /// The fraction of entries in use, estimated from a sample of entries
+    pub fn fill(&self) -> f64 {
+        let n_buckets = self.n_buckets.min(FILL_SAMPLE_SIZE / BUCKET_SIZE);
+        // Entries are only written for depths of 2 or more
+        let used = (0..n_buckets)
+            .flat_map(|index| self.bucket_at(index))
+            .filter(|entry| entry.load().0 != 0)
+            .count();
+        used as f64 / (n_buckets * BUCKET_SIZE) as f64
+    }
+
+    /// Retrieve stored count information from the cache. A collision is an
+    /// entry for the position at another depth
+    pub fn read(&self, key: u64, depth: u8) -> Access {
+        let mut access = Access::Miss;
+        for entry in self.bucket(key) {
+            if key == entry.key() {
+                if !entry.is_intact(key) {
+                    return Access::Torn;
+                }
+                let (entry_depth, count) = entry.load();
+                if depth == entry_depth {
+                    return Access::Hit(count);
+                }
+                access = Access::Collision;
+            }
+        }
+        access
+    }
+
+    /// Write a perft entry into the cache, returning whether another entry
+    /// was evicted to make room
+    pub fn write(&self, key: u64, depth: u8, count: &MoveCounter) -> bool {
+        let bucket = self.bucket(key);
+
+        // Reuse an empty slot or the slot already holding the entry
+        let mut slots = [(0, 0); BUCKET_SIZE];
+        for (i, entry) in bucket.iter().enumerate() {
+            let (entry_depth, entry_count) = entry.load();
+            if entry_depth == 0 || (entry_depth == depth && entry.key() == key) {
+                entry.store(key, depth, count);
+                return false;
+            }
+            slots[i] = (entry_depth, entry_count.nodes);
+        }
+
+        // Upper key bits are independent of the bucket index
+        let keyed = (key >> 48) as usize;
+        let victim = match self.replacement {
+            Replacement::Always => keyed % BUCKET_SIZE,
+            Replacement::Depth => lowest(&slots, |(depth, _)| depth as u64),
+            Replacement::Nodes => lowest(&slots, |(_, nodes)| nodes),
+            Replacement::TwoTier => {
+                let half = BUCKET_SIZE / 2;
+                let deep = lowest(&slots[..half], |(depth, _)| depth as u64);
+                if depth >= slots[deep].0 {
+                    deep
+                } else {
+                    half + keyed % half
+                }
+            }
+        };
+        bucket[victim].store(key, depth, count);
+        true
+    }
+}
+
+/// The index of the first slot with the lowest value
+fn lowest(slots: &[(u8, u64)], value: impl Fn((u8, u64)) -> u64) -> usize {
+    let mut index = 0;
+    for (i, &slot) in slots.iter().enumerate() {
+        if value(slot) < value(slots[index]) {
+            index = i;
+        }
+    }
+    index
+}
+
+/// Entries must consist only of atomic words, so that any bit pattern read
+/// from a cache file is a valid entry
+pub trait SizedEntry: Entry + Sized + Clone + Default + Sync + Send {
+    /// Identifies the entry layout in cache files
+    const KIND: u32;
+    /// Whether entries carry a checksum, and hits should be verified
+    const CHECKED: bool = false;
+}
+
+pub trait Entry {
+    fn key(&self) -> u64;
+    // Return depth and count info as a tuple
+    fn load(&self) -> (u8, MoveCounter);
+    // Store depth and count info
+    fn store(&self, key: u64, depth: u8, count: &MoveCounter);
+    /// Whether an entry matching the key was written in one piece. The XOR
+    /// key is all that is checked by default
+    fn is_intact(&self, _key: u64) -> bool {
+        true
+    }
+}
+
+#[derive(Default)]
+pub struct Entry2xU64 {
+    wordq_0: AtomicU64,
+    wordq_1: AtomicU64,
+}
+
+impl Clone for Entry2xU64 {
+    fn clone(&self) -> Self {
+        Self {
+            wordq_0: AtomicU64::new(self.wordq_0.load(Ordering::Relaxed)),
+            wordq_1: AtomicU64::new(self.wordq_1.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Entry for Entry2xU64 {
+    fn key(&self) -> u64 {
+        self.wordq_0() ^ self.wordq_1()
+    }
+
+    fn load(&self) -> (u8, MoveCounter) {
+        let wordq_1 = self.wordq_1();
+        let count = MoveCounter {
+            nodes: wordq_1 >> 8,
+            ..Default::default()
+        };
+        (wordq_1 as u8, count)
+    }
+
+    fn store(&self, key: u64, depth: u8, count: &MoveCounter) {
+        let wordq_1 = depth as u64 | count.nodes << 8;
+        let wordq_0 = key ^ wordq_1;
+
+        self.wordq_0.store(wordq_0, Ordering::Relaxed);
+        self.wordq_1.store(wordq_1, Ordering::Relaxed);
+    }
+}
+
+impl Entry2xU64 {
+    fn wordq_0(&self) -> u64 {
+        self.wordq_0.load(Ordering::Relaxed)
+    }
+
+    fn wordq_1(&self) -> u64 {
+        self.wordq_1.load(Ordering::Relaxed)
+    }
+}
+
+impl SizedEntry for Entry2xU64 {
+    const KIND: u32 = 2;
+}
+
+#[derive(Default)]
+pub struct Entry6xU64 {
+    wordq_0: AtomicU64,
+    wordq_1: AtomicU64, // Depth and node count
+    wordq_2: AtomicU64, // Captures and ep
+    wordq_3: AtomicU64, // Castles and promotions
+    wordq_4: AtomicU64, // Checks and discovered checks
+    wordq_5: AtomicU64, // Double checks and checkmates
+}
+
+impl Clone for Entry6xU64 {
+    fn clone(&self) -> Self {
+        Self {
+            wordq_0: AtomicU64::new(self.wordq_0.load(Ordering::Relaxed)),
+            wordq_1: AtomicU64::new(self.wordq_1.load(Ordering::Relaxed)),
+            wordq_2: AtomicU64::new(self.wordq_2.load(Ordering::Relaxed)),
+            wordq_3: AtomicU64::new(self.wordq_3.load(Ordering::Relaxed)),
+            wordq_4: AtomicU64::new(self.wordq_4.load(Ordering::Relaxed)),
+            wordq_5: AtomicU64::new(self.wordq_5.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Entry for Entry6xU64 {
+    fn key(&self) -> u64 {
+        self.wordq_0()
+            ^ self.wordq_1()
+            ^ self.wordq_2()
+            ^ self.wordq_3()
+            ^ self.wordq_4()
+            ^ self.wordq_5()
+    }
+
+    fn load(&self) -> (u8, MoveCounter) {
+        let wordq_1 = self.wordq_1();
+        let wordq_2 = self.wordq_2();
+        let wordq_3 = self.wordq_3();
+        let wordq_4 = self.wordq_4();
+        let wordq_5 = self.wordq_5();
+        let count = MoveCounter {
+            nodes: wordq_1 >> 8,
+            captures: (wordq_2 >> 32) as u32,
+            ep: wordq_2 as u32,
+            castles: (wordq_3 >> 32) as u32,
+            promotions: wordq_3 as u32,
+            checks: (wordq_4 >> 32) as u32,
+            discovered_checks: wordq_4 as u32,
+            double_checks: (wordq_5 >> 32) as u32,
+            checkmates: wordq_5 as u32,
+        };
+        (wordq_1 as u8, count)
+    }
+
+    fn store(&self, key: u64, depth: u8, count: &MoveCounter) {
+        // Upper 56 bits store node count, lowest 8 bits store depth
+        let wordq_1 = depth as u64 | count.nodes << 8;
+        let wordq_2 = (count.captures as u64) << 32 | count.ep as u64;
+        let wordq_3 = (count.castles as u64) << 32 | count.promotions as u64;
+        let wordq_4 = (count.checks as u64) << 32 | count.discovered_checks as u64;
+        let wordq_5 = (count.double_checks as u64) << 32 | count.checkmates as u64;
+        let wordq_0 = key ^ wordq_1 ^ wordq_2 ^ wordq_3 ^ wordq_4 ^ wordq_5;
+
+        self.wordq_0.store(wordq_0, Ordering::Relaxed);
+        self.wordq_1.store(wordq_1, Ordering::Relaxed);
+        self.wordq_2.store(wordq_2, Ordering::Relaxed);
+        self.wordq_3.store(wordq_3, Ordering::Relaxed);
+        self.wordq_4.store(wordq_4, Ordering::Relaxed);
+        self.wordq_5.store(wordq_5, Ordering::Relaxed);
+    }
+}
+
+impl Entry6xU64 {
+    fn wordq_0(&self) -> u64 {
+        self.wordq_0.load(Ordering::Relaxed)
+    }
+
+    fn wordq_1(&self) -> u64 {
+        self.wordq_1.load(Ordering::Relaxed)
+    }
+
+    fn wordq_2(&self) -> u64 {
+        self.wordq_2.load(Ordering::Relaxed)
+    }
+
+    fn wordq_3(&self) -> u64 {
+        self.wordq_3.load(Ordering::Relaxed)
+    }
+
+    fn wordq_4(&self) -> u64 {
+        self.wordq_4.load(Ordering::Relaxed)
+    }
+
+    fn wordq_5(&self) -> u64 {
+        self.wordq_5.load(Ordering::Relaxed)
+    }
+}
+
+impl SizedEntry for Entry6xU64 {
+    const KIND: u32 = 6;
+}
+
+/// An entry with a checksum of its key and counts in an extra word, to
+/// detect torn writes which slip through the XOR key
+#[derive(Default)]
+pub struct Checked<E: SizedEntry> {
+    entry: E,
+    check: AtomicU64,
+}
+
+impl<E: SizedEntry> Clone for Checked<E> {
+    fn clone(&self) -> Self {
+        Self {
+            entry: self.entry.clone(),
+            check: AtomicU64::new(self.check.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl<E: SizedEntry> Entry for Checked<E> {
+    fn key(&self) -> u64 {
+        self.entry.key()
+    }
+
+    fn load(&self) -> (u8, MoveCounter) {
+        self.entry.load()
+    }
+
+    fn store(&self, key: u64, depth: u8, count: &MoveCounter) {
+        // Checksum the counts as the entry keeps them, which may be fewer
+        // than given
+        let stored = E::default();
+        stored.store(key, depth, count);
+        let (_, count) = stored.load();
+
+        self.entry.store(key, depth, &count);
+        self.check
+            .store(checksum(key, depth, &count), Ordering::Relaxed);
+    }
+
+    fn is_intact(&self, key: u64) -> bool {
+        let (depth, count) = self.entry.load();
+        self.check.load(Ordering::Relaxed) == checksum(key, depth, &count)
+    }
+}
+
+impl<E: SizedEntry> SizedEntry for Checked<E> {
+    const KIND: u32 = E::KIND + 1;
+    const CHECKED: bool = true;
+}
+
+/// Hash the key with every field of the counts
+fn checksum(key: u64, depth: u8, count: &MoveCounter) -> u64 {
+    [
+        depth as u64,
+        count.nodes,
+        count.captures as u64,
+        count.ep as u64,
+        count.castles as u64,
+        count.promotions as u64,
+        count.checks as u64,
+        count.discovered_checks as u64,
+        count.double_checks as u64,
+        count.checkmates as u64,
+    ]
+    .iter()
+    .fold(key, |hash, &word| {
+        (hash ^ word)
+            .wrapping_mul(0x9e3779b97f4a7c15)
+            .rotate_left(29)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    fn count(nodes: u64) -> MoveCounter {
+        MoveCounter {
+            nodes,
+            ..Default::default()
+        }
+    }
+
+    /// Fill the first of two buckets, then write one more entry. Keys are even
+    /// so that they share the first bucket
+    fn overflow(replacement: Replacement, depth: u8, nodes: u64) -> Vec<u64> {
+        let cache = Cache::<Entry2xU64>::new(2 * size_of::<Bucket<Entry2xU64>>(), replacement, 1);
+        let entries = [(2, 5, 900), (4, 3, 100), (6, 2, 50), (8, 4, 40)];
+        for (key, depth, nodes) in entries {
+            assert!(!cache.write(key, depth, &count(nodes)));
+        }
+        assert!(cache.write(10, depth, &count(nodes)));
+
+        [2, 4, 6, 8]
+            .into_iter()
+            .filter(|&key| !matches!(cache.read(key, 0), Access::Collision))
+            .collect()
+    }
+
+    #[test_case(Replacement::Always, 3, vec![2]; "always")]
+    #[test_case(Replacement::Depth, 3, vec![6]; "depth")]
+    #[test_case(Replacement::Nodes, 3, vec![8]; "nodes")]
+    #[test_case(Replacement::TwoTier, 4, vec![4]; "two tier deep")]
+    #[test_case(Replacement::TwoTier, 2, vec![6]; "two tier shallow")]
+    fn test_replacement(replacement: Replacement, depth: u8, evicted: Vec<u64>) {
+        assert_eq!(overflow(replacement, depth, 70), evicted);
+    }
+
+    #[test_case(0, 1; "empty")]
+    #[test_case(64, 1; "one bucket")]
+    #[test_case(1 << 20, 1 << 14; "power of two")]
+    #[test_case(32_000_000, 1 << 18; "rounded down")]
+    fn test_buckets_for(size_bytes: usize, expected: usize) {
+        assert_eq!(Cache::<Entry2xU64>::buckets_for(size_bytes), expected);
+    }
+
+    #[test]
+    fn test_read_bucket() {
+        let cache = Cache::<Entry6xU64>::new(1 << 12, Replacement::Depth, 1);
+        cache.write(0x1234, 3, &count(8902));
+        cache.write(0x1234, 4, &count(197281));
+        assert!(matches!(cache.read(0x1234, 3), Access::Hit(c) if c.nodes == 8902));
+        assert!(matches!(cache.read(0x1234, 4), Access::Hit(c) if c.nodes == 197281));
+        assert!(matches!(cache.read(0x1234, 5), Access::Collision));
+        assert!(matches!(cache.read(0x4321, 3), Access::Miss));
+    }
+
+    #[test]
+    fn test_checked_entry_in_every_bucket() {
+        // Checked entries do not fill a cache line, so the buckets are padded
+        assert!(size_of::<Bucket<Checked<Entry2xU64>>>() > BUCKET_SIZE * 24);
+        let cache = Cache::<Checked<Entry2xU64>>::new(1 << 16, Replacement::Depth, 1);
+        let n_buckets = cache.n_buckets as u64;
+        let keys = |bucket: u64| (1..=BUCKET_SIZE as u64).map(move |i| bucket | i << 32);
+        let Storage::Memory(buckets) = &cache.entries else {
+            panic!("small caches are allocated on the heap")
+        };
+        for bucket in 0..n_buckets {
+            // Entries must be read from within their own bucket
+            let entries = &buckets[bucket as usize].0;
+            assert!(std::ptr::eq(
+                cache.bucket(bucket).as_ptr(),
+                entries.as_ptr()
+            ));
+            for (i, key) in keys(bucket).enumerate() {
+                assert!(!cache.write(key, 2 + i as u8, &count(key)));
+            }
+        }
+        for bucket in 0..n_buckets {
+            for (i, key) in keys(bucket).enumerate() {
+                assert!(matches!(cache.read(key, 2 + i as u8), Access::Hit(c) if c.nodes == key));
+            }
+        }
+        assert_eq!(cache.fill(), 1.0);
+    }
+
+    #[test]
+    fn test_checked_entry() {
+        let cache = Cache::<Checked<Entry2xU64>>::new(1 << 12, Replacement::Depth, 1);
+        cache.write(0x1234, 3, &count(8902));
+        assert!(matches!(cache.read(0x1234, 3), Access::Hit(c) if c.nodes == 8902));
+
+        // Tear the entry by overwriting the checksum with one of another write
+        let entry = &cache.bucket(0x1234)[0];
+        entry
+            .check
+            .store(checksum(0x1234, 3, &count(8903)), Ordering::Relaxed);
+        assert!(matches!(cache.read(0x1234, 3), Access::Torn));
+    }
+}
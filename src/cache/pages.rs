@@ -0,0 +1,176 @@
+/// Allocation of large caches on huge pages, zeroed by several threads
+use super::*;
+
+use std::ptr::NonNull;
+
+/// Size of the huge pages requested from the kernel
+pub const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+const THP_SETTING: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+
+/// The kind of pages backing a cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    /// Explicit huge pages reserved in hugetlbfs
+    Hugetlb,
+    /// Transparent huge pages, requested with madvise
+    Transparent,
+    /// Regular 4K pages
+    Small,
+}
+
+/// Buckets in an anonymous memory mapping aligned to huge pages. Pages are
+/// first touched by several threads, which spreads the page faults and, with
+/// a first touch NUMA policy, the memory across the nodes of those threads
+pub struct PageMemory<T: SizedEntry> {
+    map: NonNull<libc::c_void>,
+    map_len: usize,
+    buckets: NonNull<Bucket<T>>,
+    n_buckets: usize,
+    kind: PageKind,
+}
+
+// Safety: the buckets consist of atomic words and the mapping is owned
+unsafe impl<T: SizedEntry> Send for PageMemory<T> {}
+unsafe impl<T: SizedEntry> Sync for PageMemory<T> {}
+
+impl<T: SizedEntry> PageMemory<T> {
+    /// Map memory for the buckets, preferring explicit huge pages, then
+    /// transparent huge pages. Returns `None` if no memory could be mapped
+    pub fn allocate(n_buckets: usize, threads: usize) -> Option<Self> {
+        let bytes = (n_buckets * size_of::<Bucket<T>>()).next_multiple_of(HUGE_PAGE_SIZE);
+
+        let memory = match Self::map(bytes, bytes, libc::MAP_HUGETLB) {
+            Some((map, buckets)) => Self {
+                map,
+                map_len: bytes,
+                buckets,
+                n_buckets,
+                kind: PageKind::Hugetlb,
+            },
+            None => {
+                // Map an extra huge page, so the buckets can start on a huge
+                // page boundary
+                let map_len = bytes + HUGE_PAGE_SIZE;
+                let (map, buckets) = Self::map(map_len, bytes, 0)?;
+                // Safety: the aligned range lies within the mapping
+                let advised =
+                    unsafe { libc::madvise(buckets.as_ptr().cast(), bytes, libc::MADV_HUGEPAGE) };
+                let kind = if advised == 0 && transparent_huge_pages() {
+                    PageKind::Transparent
+                } else {
+                    log::warn!(
+                        "Huge pages are unavailable, the cache uses regular pages. \
+                         Enable transparent huge pages or reserve pages in \
+                         /proc/sys/vm/nr_hugepages for faster cache access"
+                    );
+                    PageKind::Small
+                };
+                Self {
+                    map,
+                    map_len,
+                    buckets,
+                    n_buckets,
+                    kind,
+                }
+            }
+        };
+        log::info!(
+            "Cache of {bytes} bytes allocated on {:?} pages",
+            memory.kind
+        );
+        memory.zero(threads);
+        Some(memory)
+    }
+
+    /// Map `len` bytes of anonymous memory, returning the mapping and the
+    /// first huge page boundary within it, which has room for `bytes`
+    fn map(
+        len: usize,
+        bytes: usize,
+        flags: libc::c_int,
+    ) -> Option<(NonNull<libc::c_void>, NonNull<Bucket<T>>)> {
+        // Safety: a new anonymous mapping does not alias any memory
+        let map = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | flags,
+                -1,
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return None;
+        }
+        let offset = map.align_offset(HUGE_PAGE_SIZE);
+        debug_assert!(offset + bytes <= len);
+        // Safety: the offset lies within the mapping
+        let buckets = unsafe { map.cast::<u8>().add(offset) };
+        Some((NonNull::new(map)?, NonNull::new(buckets.cast())?))
+    }
+
+    /// Zero the buckets in one chunk per thread. Anonymous memory is already
+    /// zero, but writing it makes every thread fault in its own pages
+    fn zero(&self, threads: usize) {
+        let bytes = self.n_buckets * size_of::<Bucket<T>>();
+        let chunk = bytes
+            .div_ceil(threads.max(1))
+            .next_multiple_of(HUGE_PAGE_SIZE);
+        let start = self.buckets.as_ptr() as usize;
+        std::thread::scope(|scope| {
+            for offset in (0..bytes).step_by(chunk) {
+                let len = chunk.min(bytes - offset);
+                scope.spawn(move || {
+                    // Safety: chunks are disjoint ranges within the buckets
+                    unsafe { std::ptr::write_bytes((start + offset) as *mut u8, 0, len) };
+                });
+            }
+        });
+    }
+
+    pub fn buckets(&self) -> &[Bucket<T>] {
+        // Safety: the buckets were zeroed, a valid bit pattern for entries
+        unsafe { std::slice::from_raw_parts(self.buckets.as_ptr(), self.n_buckets) }
+    }
+}
+
+impl<T: SizedEntry> Drop for PageMemory<T> {
+    fn drop(&mut self) {
+        // Safety: the mapping is owned and no buckets are borrowed
+        unsafe { libc::munmap(self.map.as_ptr(), self.map_len) };
+    }
+}
+
+/// Whether transparent huge pages can be used with madvise
+fn transparent_huge_pages() -> bool {
+    std::fs::read_to_string(THP_SETTING).is_ok_and(|setting| !setting.contains("[never]"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_zeroed() {
+        let n_buckets = 3 * HUGE_PAGE_SIZE / size_of::<Bucket<Entry2xU64>>() + 5;
+        let memory = PageMemory::<Entry2xU64>::allocate(n_buckets, 4).unwrap();
+        let buckets = memory.buckets();
+        assert_eq!(buckets.len(), n_buckets);
+        assert_eq!(buckets.as_ptr() as usize % HUGE_PAGE_SIZE, 0);
+        assert!(buckets.iter().flat_map(|b| &b.0).all(|e| e.load().0 == 0));
+    }
+
+    #[test]
+    fn test_cache_on_pages() {
+        let cache = Cache::<Entry2xU64>::new(2 * HUGE_PAGE_SIZE, Replacement::Depth, 2);
+        assert!(matches!(cache.entries, Storage::Pages(_)));
+        let count = MoveCounter {
+            nodes: 8902,
+            ..Default::default()
+        };
+        cache.write(0x1234, 3, &count);
+        assert!(matches!(cache.read(0x1234, 3), Access::Hit(c) if c.nodes == 8902));
+    }
+}
@@ -3,7 +3,8 @@
 use super::*;
 
 pub const MAX_DEPTH: usize = 50;
-pub const DEFAULT_CACHE_SIZE: usize = 32_000_000;
+pub const DEFAULT_CACHE_SIZE: usize = 32 << 20;
+pub const DEFAULT_SPLIT_DEPTH: u8 = 2;
 
 pub mod cli {
     pub use super::fen::STARTING_FEN;
@@ -130,6 +131,9 @@ pub mod bb {
 
     pub const FULL: BitBoard = BitBoard(0xffffffffffffffff);
     pub const EMPTY: BitBoard = BitBoard(0x0);
+
+    pub const LIGHT_SQUARES: BitBoard = BitBoard(0x55aa55aa55aa55aa);
+    pub const DARK_SQUARES: BitBoard = BitBoard(0xaa55aa55aa55aa55);
 }
 
 #[allow(dead_code)]
@@ -0,0 +1,273 @@
+/// Game state across moves, detecting the end of a game
+use super::*;
+
+use constants::bb::{DARK_SQUARES, LIGHT_SQUARES};
+use movegen::generate_all;
+use movelist::MoveCounter;
+use mv::Move;
+use position::Position;
+use types::ColorT;
+
+/// The state of a game after the last move. Claimable draws are reported but
+/// do not end the game, see [`GameStatus::is_over`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    /// The side to move is checkmated
+    Checkmate,
+    /// The side to move has no legal moves but is not in check
+    Stalemate,
+    /// 50 moves by each side without a capture or pawn move, claimable
+    FiftyMoves,
+    /// 75 moves by each side without a capture or pawn move
+    SeventyFiveMoves,
+    /// The position has occurred three times, claimable
+    ThreefoldRepetition,
+    /// The position has occurred five times
+    FivefoldRepetition,
+    /// Neither side has the material to checkmate
+    InsufficientMaterial,
+    /// A locked pawn position which neither king can break into
+    DeadPosition,
+}
+
+impl GameStatus {
+    /// Whether the game has ended, without either player claiming a draw
+    pub fn is_over(&self) -> bool {
+        !matches!(
+            self,
+            GameStatus::Ongoing | GameStatus::FiftyMoves | GameStatus::ThreefoldRepetition
+        )
+    }
+}
+
+/// A game played from a starting position, keeping the Zobrist keys of every
+/// position reached to detect repetitions
+///
+/// ```
+/// use rperft::{Game, GameStatus, Position};
+///
+/// let mut game = Game::new(Position::new_start_pos());
+/// for san in ["f3", "e5", "g4", "Qh4#"] {
+///     let mv = game.position().parse_move(san).unwrap();
+///     game.play(&mv);
+/// }
+/// assert_eq!(game.status(), GameStatus::Checkmate);
+/// assert_eq!(game.result(), "0-1");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Game {
+    pos: Position,
+    moves: Vec<Move>,
+    keys: Vec<u64>,
+}
+
+impl Game {
+    pub fn new(start: Position) -> Self {
+        Self {
+            pos: start,
+            moves: Vec::new(),
+            keys: vec![start.key],
+        }
+    }
+
+    /// The current position
+    pub fn position(&self) -> &Position {
+        &self.pos
+    }
+
+    /// The moves played since the starting position
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// Play a legal move in the current position
+    pub fn play(&mut self, mv: &Move) {
+        self.pos = self.pos.make_move(mv);
+        self.moves.push(*mv);
+        self.keys.push(self.pos.key);
+    }
+
+    /// The number of times the current position has occurred, including now.
+    /// Only positions since the last capture or pawn move can repeat
+    pub fn repetitions(&self) -> usize {
+        let last = self.keys.len() - 1;
+        let window = (self.pos.halfmove_clock as usize).min(last);
+        self.keys[last - window..]
+            .iter()
+            .rev()
+            .step_by(2)
+            .filter(|&&key| key == self.pos.key)
+            .count()
+    }
+
+    /// The status of the game in the current position. Checkmate and
+    /// stalemate take precedence over the move and repetition rules
+    pub fn status(&self) -> GameStatus {
+        let mut counter = MoveCounter::default();
+        generate_all(&self.pos, &mut counter);
+        if counter.nodes == 0 {
+            if self.pos.stm_checkers().is_empty() {
+                return GameStatus::Stalemate;
+            }
+            return GameStatus::Checkmate;
+        }
+
+        let repetitions = self.repetitions();
+        if repetitions >= 5 {
+            return GameStatus::FivefoldRepetition;
+        }
+        if self.pos.halfmove_clock >= 150 {
+            return GameStatus::SeventyFiveMoves;
+        }
+        if self.pos.has_insufficient_material() {
+            return GameStatus::InsufficientMaterial;
+        }
+        if self.pos.is_locked_pawn_ending() {
+            return GameStatus::DeadPosition;
+        }
+        if repetitions >= 3 {
+            return GameStatus::ThreefoldRepetition;
+        }
+        if self.pos.halfmove_clock >= 100 {
+            return GameStatus::FiftyMoves;
+        }
+        GameStatus::Ongoing
+    }
+
+    /// The result of the game as written in PGN, `*` if it is not over
+    pub fn result(&self) -> &'static str {
+        match self.status() {
+            GameStatus::Checkmate => match self.pos.stm {
+                ColorT::White => "0-1",
+                ColorT::Black => "1-0",
+            },
+            status if status.is_over() => "1/2-1/2",
+            _ => "*",
+        }
+    }
+}
+
+impl Position {
+    /// Whether neither side can checkmate with any series of legal moves,
+    /// which is the case with only kings and a single minor piece, or only
+    /// kings and bishops on squares of one colour
+    fn has_insufficient_material(&self) -> bool {
+        let (white, black) = self.white_black();
+        let heavy = white.pawn | white.rook | white.queen | black.pawn | black.rook | black.queen;
+        if heavy.is_not_empty() {
+            return false;
+        }
+        let knights = white.knight | black.knight;
+        let bishops = white.bishop | black.bishop;
+        if (knights | bishops).pop_count() <= 1 {
+            return true;
+        }
+        knights.is_empty()
+            && ((bishops & LIGHT_SQUARES).is_empty() || (bishops & DARK_SQUARES).is_empty())
+    }
+
+    /// Whether only kings and pawns are left, every pawn is blocked by
+    /// another with nothing to capture, and neither king can reach an
+    /// undefended enemy pawn. No pawn can then ever move, and kings alone
+    /// cannot checkmate
+    fn is_locked_pawn_ending(&self) -> bool {
+        let (white, black) = self.white_black();
+        let pawns = white.pawn | black.pawn;
+        if pawns.is_empty() || self.occ != pawns | white.king | black.king {
+            return false;
+        }
+
+        let white_attacks = white.pawn.nort_east() | white.pawn.nort_west();
+        let black_attacks = black.pawn.sout_east() | black.pawn.sout_west();
+        if (white.pawn.north_one() & !pawns).is_not_empty()
+            || (black.pawn.south_one() & !pawns).is_not_empty()
+            || (white_attacks & black.pawn).is_not_empty()
+            || (black_attacks & white.pawn).is_not_empty()
+        {
+            return false;
+        }
+
+        // Kings can never step onto squares attacked by enemy pawns
+        let white_region = king_region(white.king, !(white.pawn | black_attacks));
+        let black_region = king_region(black.king, !(black.pawn | white_attacks));
+        (white_region & black.pawn).is_empty() && (black_region & white.pawn).is_empty()
+    }
+}
+
+/// Flood fill the squares a king can reach by moving through allowed squares
+fn king_region(king: BitBoard, allowed: BitBoard) -> BitBoard {
+    let mut region = king;
+    loop {
+        let next = region | (region.generate_king_attacks() & allowed);
+        if next == region {
+            return region;
+        }
+        region = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    #[test_case(constants::fen::STARTING_FEN, GameStatus::Ongoing; "startpos")]
+    #[test_case("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1", GameStatus::Checkmate; "checkmate")]
+    #[test_case("7k/6Q1/6K1/8/8/8/8/8 b - - 150 90", GameStatus::Checkmate; "checkmate on move 75")]
+    #[test_case("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", GameStatus::Stalemate; "stalemate")]
+    #[test_case("8/8/4k3/8/8/4K3/8/R7 w - - 99 80", GameStatus::Ongoing; "49.5 moves")]
+    #[test_case("8/8/4k3/8/8/4K3/8/R7 w - - 100 80", GameStatus::FiftyMoves; "fifty moves")]
+    #[test_case("8/8/4k3/8/8/4K3/8/R7 w - - 150 80", GameStatus::SeventyFiveMoves; "seventy five moves")]
+    #[test_case("8/8/4k3/8/8/4K3/8/8 w - - 0 1", GameStatus::InsufficientMaterial; "bare kings")]
+    #[test_case("8/8/4k3/8/8/4K3/8/6N1 w - - 0 1", GameStatus::InsufficientMaterial; "knight")]
+    #[test_case("8/8/4k3/8/8/4K3/8/5B2 w - - 0 1", GameStatus::InsufficientMaterial; "bishop")]
+    #[test_case("8/3b4/4k3/8/8/4K3/8/5B2 w - - 0 1", GameStatus::InsufficientMaterial;
+        "same coloured bishops")]
+    #[test_case("8/2b5/4k3/8/8/4K3/8/5B2 w - - 0 1", GameStatus::Ongoing; "opposite coloured bishops")]
+    #[test_case("8/8/4k3/8/8/4K3/8/5NN1 w - - 0 1", GameStatus::Ongoing; "two knights")]
+    #[test_case("8/8/4k3/8/8/4K3/4P3/8 w - - 0 1", GameStatus::Ongoing; "pawn")]
+    #[test_case("8/8/4k3/1p1p1p1p/1P1P1P1P/4K3/8/8 w - - 0 1", GameStatus::DeadPosition; "locked pawns")]
+    #[test_case("8/8/4k3/1p1p1p2/1P1P1P2/4K3/8/8 w - - 0 1", GameStatus::Ongoing; "king breaks in")]
+    #[test_case("8/8/4k3/1p1p1p2/1P1P1P1P/4K3/8/8 w - - 0 1", GameStatus::Ongoing; "free pawn")]
+    #[test_case("8/8/4k3/2pp4/1P1P4/4K3/8/8 w - - 0 1", GameStatus::Ongoing; "pawn capture")]
+    fn test_status(fen: &str, expected: GameStatus) {
+        let game = Game::new(Position::from_fen(fen).unwrap());
+        assert_eq!(game.status(), expected);
+    }
+
+    #[test]
+    fn test_repetition() {
+        let mut game = Game::new(Position::new_start_pos());
+        let shuffle = ["Nf3", "Nf6", "Ng1", "Ng8"];
+        let mut statuses = Vec::new();
+        for san in shuffle.iter().cycle().take(16) {
+            let mv = game.position().parse_move(san).unwrap();
+            game.play(&mv);
+            statuses.push(game.status());
+        }
+        assert_eq!(game.repetitions(), 5);
+        assert_eq!(statuses[3], GameStatus::Ongoing);
+        assert_eq!(statuses[7], GameStatus::ThreefoldRepetition);
+        assert_eq!(statuses[4], GameStatus::Ongoing);
+        assert_eq!(statuses[8], GameStatus::ThreefoldRepetition);
+        assert_eq!(statuses[15], GameStatus::FivefoldRepetition);
+        assert_eq!(game.result(), "1/2-1/2");
+
+        // A pawn move makes earlier positions unreachable
+        let mv = game.position().parse_move("e4").unwrap();
+        game.play(&mv);
+        assert_eq!(game.repetitions(), 1);
+        assert_eq!(game.status(), GameStatus::Ongoing);
+    }
+
+    #[test_case(GameStatus::Ongoing, false)]
+    #[test_case(GameStatus::FiftyMoves, false)]
+    #[test_case(GameStatus::ThreefoldRepetition, false)]
+    #[test_case(GameStatus::SeventyFiveMoves, true)]
+    #[test_case(GameStatus::DeadPosition, true)]
+    fn test_is_over(status: GameStatus, expected: bool) {
+        assert_eq!(status.is_over(), expected);
+    }
+}
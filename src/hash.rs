@@ -1,14 +1,13 @@
 /// Methods to generate and update the Zobrist hash using the Polyglot format.
 /// http://hgm.nubati.net/book_format.html
 use super::*;
-use constants::bb;
 use position::states::Color;
 use position::Position;
 use types::{ColorT, PieceT};
 
 impl Position {
     /// Generate a Zobrist key, call during position initialization and use update methods during .makemove
-    pub fn generate_zobrist_key(&self) -> u64 {
+    pub(crate) fn generate_zobrist_key(&self) -> u64 {
         let mut key = 0;
 
         // Organize BBs into arrays to allow convenient access of hash array
@@ -30,10 +29,8 @@ impl Position {
         }
 
         // Hash castling
-        for (sq, hash_idx) in std::iter::zip([bb::H1, bb::A1, bb::H8, bb::A8], 768..=771) {
-            if (self.castling_rights & sq).is_not_empty() {
-                key ^= HASH_KEYS[hash_idx]
-            }
+        for sq in self.castling_rights {
+            key ^= CASTLING_KEYS[sq.to_sq()];
         }
 
         // Hash ep
@@ -71,39 +68,38 @@ impl Position {
     }
 
     // Update zobrist hash upon move turn
-    pub fn turn_key_update(&mut self) {
+    pub(crate) fn turn_key_update(&mut self) {
         self.key ^= HASH_KEYS[780];
     }
 
     /// Update at both source and target squares for the piece
-    pub fn move_key_update(&mut self, moved_pt: PieceT, from: BitBoard, to: BitBoard, wtm: bool) {
+    pub(crate) fn move_key_update(
+        &mut self,
+        moved_pt: PieceT,
+        from: BitBoard,
+        to: BitBoard,
+        wtm: bool,
+    ) {
         let idx = PT_TO_KEY_INDEX_MAP[moved_pt as usize] * 2 + wtm as usize;
         self.key ^= HASH_KEYS[64 * idx + from.to_sq()];
         self.key ^= HASH_KEYS[64 * idx + to.to_sq()];
     }
 
     /// Update hash for a single bitflip
-    pub fn square_key_update(&mut self, pt: PieceT, sq: BitBoard, wtm: bool) {
+    pub(crate) fn square_key_update(&mut self, pt: PieceT, sq: BitBoard, wtm: bool) {
         let idx = PT_TO_KEY_INDEX_MAP[pt as usize] * 2 + wtm as usize;
         self.key ^= HASH_KEYS[64 * idx + sq.to_sq()];
     }
 
     /// Update hash for an en passant square update
-    pub fn ep_key_update<T: Color>(&mut self) {
+    pub(crate) fn ep_key_update<T: Color>(&mut self) {
         self.key ^= self.ep_hash::<T>();
     }
 
     /// Update hash for an update to castling rights
-    pub fn castling_key_update(&mut self, prev: BitBoard) {
-        let mut diff = self.castling_rights ^ prev;
-        while diff.is_not_empty() {
-            match diff.pop_ls1b_index() {
-                7 => self.key ^= HASH_KEYS[768],  // White kingside
-                0 => self.key ^= HASH_KEYS[769],  // White queenside
-                63 => self.key ^= HASH_KEYS[770], // Black kingside
-                56 => self.key ^= HASH_KEYS[771], // Black queenside
-                _ => panic!("Unrecognised bit in castling rights"),
-            }
+    pub(crate) fn castling_key_update(&mut self, prev: BitBoard) {
+        for sq in self.castling_rights ^ prev {
+            self.key ^= CASTLING_KEYS[sq.to_sq()];
         }
     }
 }
@@ -112,6 +108,28 @@ impl Position {
 // index used by the Polyglot hash table
 const PT_TO_KEY_INDEX_MAP: [usize; 7] = [0, 0, 3, 1, 2, 4, 5];
 
+// Castling rights are hashed by the square of the castling rook. Rooks in the
+// corners use the Polyglot keys, the remaining Chess960 rook squares use keys
+// generated with splitmix64
+const CASTLING_KEYS: [u64; 64] = {
+    let mut keys = [0; 64];
+    let mut state: u64 = 0x9e3779b97f4a7c15;
+    let mut sq = 0;
+    while sq < 64 {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        keys[sq] = z ^ (z >> 31);
+        sq += 1;
+    }
+    keys[7] = HASH_KEYS[768]; // White kingside
+    keys[0] = HASH_KEYS[769]; // White queenside
+    keys[63] = HASH_KEYS[770]; // Black kingside
+    keys[56] = HASH_KEYS[771]; // Black queenside
+    keys
+};
+
 // The pseudo-random hash keys used by the Polyglot program. We can use these
 // to test that our hashing algorithm is working correctly or use these for all
 // our Zobrist hashes.
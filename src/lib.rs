@@ -1,3 +1,15 @@
+//! RPerft is a reasonably fast legal move generator and perft driver.
+//!
+//! ```
+//! use rperft::Position;
+//!
+//! rperft::initialize();
+//! let pos = Position::new_start_pos();
+//! for mv in pos.legal_moves() {
+//!     let child = pos.make_move(&mv);
+//!     assert_eq!(child.legal_moves().count(), 20);
+//! }
+//! ```
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
@@ -8,7 +20,9 @@ mod bitboard;
 mod cache;
 #[allow(dead_code)]
 mod constants;
+mod game;
 mod hash;
+#[cfg_attr(USE_PEXT, allow(dead_code))]
 mod magics;
 mod makemove;
 mod movegen;
@@ -17,12 +31,23 @@ mod movelist;
 mod mv;
 #[allow(dead_code)]
 pub mod perft;
+pub mod pgn;
 #[allow(dead_code)]
 mod position;
+mod san;
 mod tables;
 mod types;
 
-use bitboard::BitBoard;
+pub use bitboard::BitBoard;
+pub use cache::Replacement;
+pub use game::{Game, GameStatus};
+pub use makemove::Undo;
+pub use movegen::{generate, generate_all, generate_pseudo_legal, stages, Backend, LegalFilter};
+pub use movelist::{MoveCounter, MoveList, MoveVec};
+pub use mv::Move;
+pub use position::{FenError, FenField, Position, Violation};
+pub use san::MoveParseError;
+pub use types::{ColorT, MoveT, PieceT};
 
 pub use constants::cli::*;
 pub use magics::initialize;
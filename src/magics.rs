@@ -16,9 +16,7 @@ trait Magic {
 
     fn init(&mut self) {
         for sq in 0..64 {
-            let magic = Self::magic(sq);
             let mask = Self::mask(sq);
-            let shift = Self::shift(sq);
 
             let n_bits = mask.count_ones();
             let n_permutations = 1 << n_bits;
@@ -38,11 +36,11 @@ trait Magic {
                 let key;
                 #[cfg(USE_PEXT)]
                 {
-                    key = unsafe { std::arch::x86_64::_pext_u64(occ, *mask) as usize }
+                    key = unsafe { std::arch::x86_64::_pext_u64(occ, mask) as usize }
                 };
                 #[cfg(not(USE_PEXT))]
                 {
-                    key = (occ.wrapping_mul(magic) >> shift) as usize
+                    key = (occ.wrapping_mul(Self::magic(sq)) >> Self::shift(sq)) as usize
                 };
                 self.write(sq, key, occ);
             }
@@ -53,7 +51,7 @@ trait Magic {
     fn lookup(&self, sq: BitBoard, occ: BitBoard) -> BitBoard {
         let sq = sq.get_ls1b_index();
         let key = unsafe { std::arch::x86_64::_pext_u64(occ.0, Self::mask(sq)) };
-        self.lookup(sq, key as usize)
+        self.lookup_table(sq, key as usize)
     }
 
     #[cfg(not(USE_PEXT))]
@@ -123,15 +121,15 @@ impl Magic for BishopTable {
 }
 
 lazy_static! {
-    static ref ROOK_ATTACKS: RookTable = {
+    static ref ROOK_ATTACKS: Box<RookTable> = {
         let mut t = Box::new(RookTable::new());
         t.init();
-        *t
+        t
     };
-    static ref BISHOP_ATTACKS: BishopTable = {
+    static ref BISHOP_ATTACKS: Box<BishopTable> = {
         let mut t = Box::new(BishopTable::new());
         t.init();
-        *t
+        t
     };
     static ref BETWEEN_TABLES: [[BitBoard; 64]; 64] = {
         let mut tables = [[BitBoard(0); 64]; 64];
@@ -1,4 +1,6 @@
-use clap::{value_parser, Arg, ArgAction, Command};
+use std::path::PathBuf;
+
+use clap::{parser::ValueSource, value_parser, Arg, ArgAction, Command};
 use rperft::*;
 
 fn main() {
@@ -26,12 +28,38 @@ fn main() {
     let cache_size_arg = Arg::new("cache")
         .short('c')
         .long("cache")
-        .default_value("32000000")
-        .value_name("N_BYTES")
-        .value_parser(value_parser!(usize))
+        .default_value("32M")
+        .value_name("SIZE")
+        .value_parser(perft::parse_cache_size)
         .help(
-            "Size of the cache in bytes. \n\
-             If set to 0, caching is disabled",
+            "Size of the cache in bytes, or with a K, M or G suffix like 512M or 4G. \n\
+             Rounded down to a power of two. If set to 0, caching is disabled",
+        )
+        .next_line_help(true);
+
+    let cache_file_arg = Arg::new("cache-file")
+        .long("cache-file")
+        .value_name("FILE")
+        .help(
+            "Keep the cache in a file, reusing its entries in later runs. \n\
+             A new file is created with the cache size, an existing file keeps its size",
+        )
+        .next_line_help(true);
+
+    let replace_arg = Arg::new("replace")
+        .long("replace")
+        .default_value("depth")
+        .value_name("STRATEGY")
+        .value_parser(value_parser!(Replacement))
+        .help("Which entry of a full cache bucket is replaced by a new result");
+
+    let verify_flag = Arg::new("verify-cache")
+        .long("verify-cache")
+        .action(ArgAction::SetTrue)
+        .help(
+            "Checksum cache entries and recompute a sample of cache hits up to \n\
+             depth 4 without the cache, counting torn entries and wrong cached \n\
+             counts. Slows down perft",
         )
         .next_line_help(true);
 
@@ -41,12 +69,58 @@ fn main() {
         .help("Count additional features like captures, ep etc. Impacts perft speed")
         .next_line_help(true);
 
+    let movegen_arg = Arg::new("movegen")
+        .long("movegen")
+        .default_value("legal")
+        .value_name("BACKEND")
+        .value_parser(value_parser!(Backend))
+        .help(
+            "Move generator used by the search. The pseudo-legal generator tests each \n\
+             move for legality, to cross-check the counts of the legal generator",
+        )
+        .next_line_help(true);
+
     let singlethread_flag = Arg::new("singlethread")
         .long("single")
         .action(ArgAction::SetTrue)
         .help("Use only a single thread")
         .next_line_help(true);
 
+    let threads_arg = Arg::new("threads")
+        .short('t')
+        .long("threads")
+        .value_name("N")
+        .value_parser(value_parser!(u16).range(1..))
+        .conflicts_with("singlethread")
+        .help("Number of worker threads, one per core by default");
+
+    let pin_flag = Arg::new("pin")
+        .long("pin")
+        .action(ArgAction::SetTrue)
+        .help("Pin each worker thread to its own core");
+
+    let scaling_flag = Arg::new("scaling")
+        .long("scaling")
+        .action(ArgAction::SetTrue)
+        .conflicts_with("singlethread")
+        .help(
+            "Run the position with 1, 2, 4, ... up to the number of threads, \n\
+             reporting the speedup and efficiency of each thread count",
+        )
+        .next_line_help(true);
+
+    let split_depth_arg = Arg::new("split-depth")
+        .long("split-depth")
+        .default_value("2")
+        .value_name("PLIES")
+        .value_parser(value_parser!(u8).range(1..))
+        .help(
+            "Plies below the root at which the search is split into jobs for the threads. \n\
+             Deeper splits balance the load better at the cost of more jobs. Searches \n\
+             no deeper than the split depth plus one run on a single thread",
+        )
+        .next_line_help(true);
+
     let bench_flag = Arg::new("bench")
         .long("bench")
         .action(ArgAction::SetTrue)
@@ -65,6 +139,54 @@ fn main() {
         )
         .next_line_help(true);
 
+    let epd_arg = Arg::new("epd")
+        .long("epd")
+        .value_name("FILE")
+        .help(
+            "Run the perft suite in an EPD file and verify the expected counts. \n\
+             Depths above the depth argument are skipped if it is given",
+        )
+        .next_line_help(true);
+
+    let output_arg = Arg::new("output")
+        .short('o')
+        .long("output")
+        .default_value("table")
+        .value_name("FORMAT")
+        .value_parser(value_parser!(perft::OutputFormat))
+        .help("Format of the results. Applies to perft runs, divide and the benchmark suite")
+        .next_line_help(true);
+
+    let divide_flag = Arg::new("divide")
+        .long("divide")
+        .action(ArgAction::SetTrue)
+        .help("List the node count under each root move")
+        .next_line_help(true);
+
+    let perftree_cmd = Command::new("perftree")
+        .about("Divide a position in the format expected by the perftree tool")
+        .arg(
+            Arg::new("depth")
+                .required(true)
+                .value_parser(value_parser!(u8))
+                .help("Depth of the search"),
+        )
+        .arg(
+            Arg::new("fen")
+                .required(true)
+                .help("Fen string of the position"),
+        )
+        .arg(Arg::new("moves").help("Space separated UCI moves to apply to the position"));
+
+    let pgn_cmd = Command::new("pgn")
+        .about("Replay every game of a PGN file and report throughput and illegal moves")
+        .arg(
+            Arg::new("file")
+                .required(true)
+                .value_name("FILE")
+                .help("Path of the PGN file"),
+        );
+
     let matches = Command::new("RPerft")
         .version(VERSION)
         .author(AUTHOR)
@@ -72,12 +194,40 @@ fn main() {
         .arg(fen_arg)
         .arg(depth_arg)
         .arg(cache_size_arg)
+        .arg(cache_file_arg)
+        .arg(replace_arg)
+        .arg(verify_flag)
         .arg(detailed_flag)
+        .arg(movegen_arg)
         .arg(singlethread_flag)
+        .arg(threads_arg)
+        .arg(pin_flag)
+        .arg(scaling_flag)
+        .arg(split_depth_arg)
         .arg(bench_flag)
         .arg(deep_flag)
+        .arg(divide_flag)
+        .arg(epd_arg)
+        .arg(output_arg)
+        .subcommand(perftree_cmd)
+        .subcommand(pgn_cmd)
         .get_matches();
 
+    if let Some(("perftree", sub_matches)) = matches.subcommand() {
+        let depth = sub_matches.get_one::<u8>("depth").expect("required arg");
+        let fen = sub_matches.get_one::<String>("fen").expect("required arg");
+        let moves = sub_matches.get_one::<String>("moves");
+        perft::perftree_wrapper(*depth, fen, moves.map(|s| s.as_str()));
+        return;
+    }
+    if let Some(("pgn", sub_matches)) = matches.subcommand() {
+        let path = sub_matches.get_one::<String>("file").expect("required arg");
+        if !pgn::replay_pgn_file(path) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let fen = matches
         .get_many("fen")
         .expect("default args")
@@ -88,15 +238,74 @@ fn main() {
         .join(" ");
 
     let depth = matches.get_one::<u8>("depth").expect("default arg");
-    let cache_size = matches.get_one::<usize>("cache").expect("default arg");
-    let multithreading = !matches.get_flag("singlethread");
+    let cache = perft::CacheOptions {
+        size: *matches.get_one::<usize>("cache").expect("default arg"),
+        file: matches.get_one::<String>("cache-file").map(PathBuf::from),
+        replacement: *matches
+            .get_one::<Replacement>("replace")
+            .expect("default arg"),
+        verify: matches.get_flag("verify-cache"),
+    };
+    let threads = perft::ThreadOptions {
+        multithreading: !matches.get_flag("singlethread"),
+        threads: matches.get_one::<u16>("threads").map(|&n| n as usize),
+        split_depth: *matches.get_one::<u8>("split-depth").expect("default arg"),
+        pin: matches.get_flag("pin"),
+    };
     let bench = matches.get_flag("bench");
     let deep = matches.get_flag("deep");
     let detailed = matches.get_flag("detailed");
+    let divide = matches.get_flag("divide");
+    let backend = *matches.get_one::<Backend>("movegen").expect("default arg");
+    let output = matches
+        .get_one::<perft::OutputFormat>("output")
+        .expect("default arg");
 
     if bench {
-        perft::run_perft_benchmark_suite(*cache_size, multithreading, deep, detailed);
+        perft::run_perft_benchmark_suite(&cache, &threads, deep, detailed, backend, *output);
+        return;
+    }
+    if matches.get_flag("scaling") {
+        perft::run_scaling_benchmark(
+            fen.as_str(),
+            *depth,
+            &cache,
+            &threads,
+            detailed,
+            backend,
+            *output,
+        );
+        return;
+    }
+    if let Some(path) = matches.get_one::<String>("epd") {
+        let max_depth = match matches.value_source("depth") {
+            Some(ValueSource::CommandLine) => Some(*depth),
+            _ => None,
+        };
+        if !perft::run_epd_suite(path, max_depth, &cache, &threads, backend) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if divide {
+        perft::perft_divide_wrapper(
+            fen.as_str(),
+            *depth,
+            &cache,
+            &threads,
+            detailed,
+            backend,
+            *output,
+        );
         return;
     }
-    perft::perft_wrapper(fen.as_str(), *depth, *cache_size, multithreading, detailed);
+    perft::perft_wrapper(
+        fen.as_str(),
+        *depth,
+        &cache,
+        &threads,
+        detailed,
+        backend,
+        *output,
+    );
 }
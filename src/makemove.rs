@@ -1,117 +1,361 @@
 /// Make move function for applying a move to a position
 use super::*;
 
+use constants::file::*;
 use mv::Move;
 use position::states::*;
 use position::Position;
 use types::{ColorT, MoveT, PieceT};
 
+/// The state of a position which cannot be recovered from a move, saved by
+/// [`Position::do_move`] so the move can be taken back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Undo {
+    captured_pt: Option<PieceT>,
+    castling_rights: BitBoard,
+    ep_sq: BitBoard,
+    halfmove_clock: u8,
+    fullmove_clock: u8,
+    key: u64,
+}
+
 impl Position {
-    /// Create a new position by applying move data to a position
+    /// Create a new position by applying move data to a position. The move
+    /// must be legal in the position
+    ///
+    /// ```
+    /// use rperft::Position;
+    ///
+    /// let pos = Position::new_start_pos();
+    /// let mv = pos.legal_moves().find(|mv| mv.to_algebraic() == "e2e4").unwrap();
+    /// let new_pos = pos.make_move(&mv);
+    /// assert_eq!(
+    ///     new_pos.to_fen(),
+    ///     "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+    /// );
+    /// ```
     pub fn make_move(&self, mv: &Move) -> Self {
+        let mut new_pos = *self;
+        match self.stm {
+            ColorT::White => new_pos.do_move_inner::<White, Black, true>(mv),
+            ColorT::Black => new_pos.do_move_inner::<Black, White, true>(mv),
+        };
+        new_pos
+    }
+
+    /// Create a new position by applying a legal move, without updating the
+    /// Zobrist key. Faster than [`Position::make_move`] for positions which
+    /// are only counted, such as the leaves of perft, but the key of the
+    /// new position is stale
+    pub(crate) fn make_move_unhashed(&self, mv: &Move) -> Self {
+        let mut new_pos = *self;
+        match self.stm {
+            ColorT::White => new_pos.do_move_inner::<White, Black, false>(mv),
+            ColorT::Black => new_pos.do_move_inner::<Black, White, false>(mv),
+        };
+        new_pos
+    }
+
+    /// Apply a legal move to the position in place, returning the state
+    /// needed to take the move back with [`Position::undo_move`]
+    ///
+    /// ```
+    /// use rperft::Position;
+    ///
+    /// let mut pos = Position::new_start_pos();
+    /// let mv = pos.legal_moves().find(|mv| mv.to_algebraic() == "g1f3").unwrap();
+    /// let undo = pos.do_move(&mv);
+    /// assert_eq!(pos.to_fen(), "rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R b KQkq - 1 1");
+    /// pos.undo_move(&mv, undo);
+    /// assert_eq!(pos, Position::new_start_pos());
+    /// ```
+    pub fn do_move(&mut self, mv: &Move) -> Undo {
+        match self.stm {
+            ColorT::White => self.do_move_inner::<White, Black, true>(mv),
+            ColorT::Black => self.do_move_inner::<Black, White, true>(mv),
+        }
+    }
+
+    /// Take back the last move applied with [`Position::do_move`], restoring
+    /// the position exactly
+    pub fn undo_move(&mut self, mv: &Move, undo: Undo) {
         match self.stm {
-            ColorT::White => self.make_move_inner::<White, Black>(mv),
-            ColorT::Black => self.make_move_inner::<Black, White>(mv),
+            ColorT::White => self.undo_move_inner::<Black>(mv, undo),
+            ColorT::Black => self.undo_move_inner::<White>(mv, undo),
         }
     }
 
     #[inline(always)]
-    fn make_move_inner<C1: Color, C2: Color>(&self, mv: &Move) -> Self {
-        let mut new_pos = *self;
+    fn do_move_inner<C1: Color, C2: Color, const HASH: bool>(&mut self, mv: &Move) -> Undo {
         // Unpack move data
-        let to = mv.to();
         let from = mv.from();
         let mt = mv.mt();
-        let captured_pt = new_pos.them.pt_at(to);
-        let moved_pt = new_pos.us.pt_at(from).expect("is occupied");
+        let (to, castle_rook) = match mt {
+            MoveT::KSCastle | MoveT::QSCastle => {
+                let (king_to, rook_from, rook_to) = self.castle_squares::<C1>(mv);
+                (king_to, Some((rook_from, rook_to)))
+            }
+            _ => (mv.to(), None),
+        };
+        let captured_pt = self.them.pt_at(to);
+        let moved_pt = self.us.pt_at(from).expect("is occupied");
+
+        let undo = Undo {
+            captured_pt,
+            castling_rights: self.castling_rights,
+            ep_sq: self.ep_sq,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_clock: self.fullmove_clock,
+            key: self.key,
+        };
 
         // Undo current ep key before position is modified
-        new_pos.ep_key_update::<C1>();
+        if HASH {
+            self.ep_key_update::<C1>();
+        }
 
         // Increment clocks
-        new_pos.halfmove_clock += 1;
-        new_pos.fullmove_clock += new_pos.stm as u8;
+        self.halfmove_clock += 1;
+        self.fullmove_clock += self.stm as u8;
 
         // Source squares must be free and target squares must be occupied
-        new_pos.free |= from;
-        new_pos.free &= !to;
+        self.free |= from;
+        self.free &= !to;
 
-        // Our bitboards must be flipped at the target and source
-        let move_mask = from | to;
-        new_pos.us[moved_pt] ^= move_mask;
-        new_pos.us.all ^= move_mask;
-        new_pos.move_key_update(moved_pt, from, to, new_pos.wtm);
+        // Our bitboards must be flipped at the target and source. A Chess960
+        // castling king may not move at all, so use xor
+        let move_mask = from ^ to;
+        self.us[moved_pt] ^= move_mask;
+        self.us.all ^= move_mask;
+        if HASH {
+            self.move_key_update(moved_pt, from, to, self.wtm);
+        }
 
         // Reset halfmove clock on pawn moves, remove castle rights on king moves
         match moved_pt {
-            PieceT::Pawn => new_pos.halfmove_clock = 0,
-            PieceT::King => new_pos.castling_rights &= !C1::rank_1(),
+            PieceT::Pawn => self.halfmove_clock = 0,
+            PieceT::King => self.castling_rights &= !C1::rank_1(),
             _ => (),
         }
 
         // If the rooks have moved, remove right to castle on that side
-        new_pos.castling_rights &= !from;
+        self.castling_rights &= !from;
 
         // Set ep target to empty, set later if dbl pawn push
-        new_pos.ep_sq = constants::bb::EMPTY;
+        self.ep_sq = constants::bb::EMPTY;
 
         // Captures, excluding en passant
         if let Some(pt) = captured_pt {
-            new_pos.them[pt] ^= to;
-            new_pos.them.all ^= to;
-            new_pos.square_key_update(pt, to, !new_pos.wtm);
+            self.them[pt] ^= to;
+            self.them.all ^= to;
+            if HASH {
+                self.square_key_update(pt, to, !self.wtm);
+            }
             // Remove castling right if rook has been captured
-            new_pos.castling_rights &= !to;
-            new_pos.halfmove_clock = 0;
+            self.castling_rights &= !to;
+            self.halfmove_clock = 0;
         }
 
         // Promotions
         if mv.is_promo() {
             let promo_pt = mv.promo_pt();
-            new_pos.us[promo_pt] ^= to;
-            new_pos.us.pawn ^= to;
-            new_pos.square_key_update(PieceT::Pawn, to, new_pos.wtm);
-            new_pos.square_key_update(promo_pt, to, new_pos.wtm);
+            self.us[promo_pt] ^= to;
+            self.us.pawn ^= to;
+            if HASH {
+                self.square_key_update(PieceT::Pawn, to, self.wtm);
+            }
+            if HASH {
+                self.square_key_update(promo_pt, to, self.wtm);
+            }
         }
 
         // Execute special actions
         match mt {
             MoveT::DoublePawnPush => {
                 // Ep target is one square behind dbl push target
-                new_pos.ep_sq = C1::back_one(to);
+                self.ep_sq = C1::back_one(to);
             }
 
             MoveT::KSCastle | MoveT::QSCastle => {
-                let (rook_from, rook_to) = if let MoveT::KSCastle = mt {
-                    (to.east_one(), to.west_one()) // Short Castle
-                } else {
-                    (to.west_two(), to.east_one()) // Long Castle
-                };
-                let mask = rook_from | rook_to;
-                new_pos.us.rook ^= mask;
-                new_pos.us.all ^= mask;
-                new_pos.free ^= mask;
-                new_pos.move_key_update(PieceT::Rook, rook_from, rook_to, new_pos.wtm);
+                let (rook_from, rook_to) = castle_rook.expect("castle has a rook move");
+                // The rook may land on or start from the king's squares, so
+                // recompute the free squares from the piece bitboards
+                let mask = rook_from ^ rook_to;
+                self.us.rook ^= mask;
+                self.us.all ^= mask;
+                self.free = !(self.us.all | self.them.all);
+                if HASH {
+                    self.move_key_update(PieceT::Rook, rook_from, rook_to, self.wtm);
+                }
             }
 
             MoveT::EnPassant => {
                 let ep_sq = C1::back_one(to);
-                new_pos.them.pawn ^= ep_sq;
-                new_pos.them.all ^= ep_sq;
-                new_pos.free ^= ep_sq;
-                new_pos.square_key_update(PieceT::Pawn, ep_sq, !new_pos.wtm);
+                self.them.pawn ^= ep_sq;
+                self.them.all ^= ep_sq;
+                self.free ^= ep_sq;
+                if HASH {
+                    self.square_key_update(PieceT::Pawn, ep_sq, !self.wtm);
+                }
             }
 
             _ => (),
         }
 
-        new_pos.occ = !new_pos.free;
+        self.occ = !self.free;
         // Change the turn and state
-        new_pos.change_state();
+        self.change_state();
         // Update key
-        new_pos.turn_key_update();
-        new_pos.ep_key_update::<C2>();
-        new_pos.castling_key_update(self.castling_rights);
-        new_pos
+        if HASH {
+            self.turn_key_update();
+            self.ep_key_update::<C2>();
+            self.castling_key_update(undo.castling_rights);
+        }
+        undo
+    }
+
+    /// Reverse the piece movements of a move made by `C`, then restore the
+    /// state which cannot be recomputed from the undo record
+    #[inline(always)]
+    fn undo_move_inner<C: Color>(&mut self, mv: &Move, undo: Undo) {
+        self.change_state();
+
+        let from = mv.from();
+        let mt = mv.mt();
+        let to = match mt {
+            MoveT::KSCastle | MoveT::QSCastle => {
+                let (king_to, rook_from, rook_to) = self.castle_squares::<C>(mv);
+                let mask = rook_from ^ rook_to;
+                self.us.rook ^= mask;
+                self.us.all ^= mask;
+                king_to
+            }
+            _ => mv.to(),
+        };
+
+        // Demote the promoted piece back to a pawn before moving it back
+        if mv.is_promo() {
+            self.us[mv.promo_pt()] ^= to;
+            self.us.pawn ^= to;
+        }
+
+        let moved_pt = match mt {
+            MoveT::KSCastle | MoveT::QSCastle => PieceT::King,
+            _ => self.us.pt_at(to).expect("is occupied"),
+        };
+        let move_mask = from ^ to;
+        self.us[moved_pt] ^= move_mask;
+        self.us.all ^= move_mask;
+
+        // Replace the captured piece
+        if let MoveT::EnPassant = mt {
+            let ep_sq = C::back_one(to);
+            self.them.pawn ^= ep_sq;
+            self.them.all ^= ep_sq;
+        } else if let Some(pt) = undo.captured_pt {
+            self.them[pt] ^= to;
+            self.them.all ^= to;
+        }
+
+        self.occ = self.us.all | self.them.all;
+        self.free = !self.occ;
+        self.castling_rights = undo.castling_rights;
+        self.ep_sq = undo.ep_sq;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.fullmove_clock = undo.fullmove_clock;
+        self.key = undo.key;
+    }
+
+    /// Return the king target, rook source and rook target squares of a
+    /// castle. Chess960 castles encode the rook source as the move target,
+    /// otherwise the rook starts in the corner
+    #[inline(always)]
+    pub(crate) fn castle_squares<C: Color>(&self, mv: &Move) -> (BitBoard, BitBoard, BitBoard) {
+        let rank = C::rank_1();
+        if let MoveT::KSCastle = mv.mt() {
+            let rook_from = if self.chess960 {
+                mv.to()
+            } else {
+                rank & FILE_H
+            };
+            (rank & FILE_G, rook_from, rank & FILE_F)
+        } else {
+            let rook_from = if self.chess960 {
+                mv.to()
+            } else {
+                rank & FILE_A
+            };
+            (rank & FILE_C, rook_from, rank & FILE_D)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use movelist::MoveVec;
+    use test_case::test_case;
+
+    /// Xorshift generator, to play reproducible random games
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    #[test_case(constants::fen::STARTING_FEN; "startpos")]
+    #[test_case(constants::fen::TEST_2; "testpos2")]
+    #[test_case(constants::fen::TEST_3; "testpos3")]
+    #[test_case(constants::fen::TEST_4; "testpos4")]
+    #[test_case(constants::fen::TEST_5; "testpos5")]
+    #[test_case(constants::fen::TEST_6; "testpos6")]
+    #[test_case("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"; "chess960")]
+    fn test_do_undo_matches_copy_make(fen: &str) {
+        let start = Position::from_fen(fen).unwrap();
+        for seed in 1..=20u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9e3779b97f4a7c15));
+            let mut pos = start;
+            let mut history = Vec::new();
+
+            for _ in 0..200 {
+                let mut moves = MoveVec::new();
+                movegen::generate_all(&pos, &mut moves);
+                if moves.is_empty() {
+                    break;
+                }
+                // Every move must be taken back exactly, and match copy-make
+                for mv in moves.iter() {
+                    let before = pos;
+                    let undo = pos.do_move(mv);
+                    assert_eq!(pos, before.make_move(mv), "{} in {}", mv, before.to_fen());
+                    assert_eq!(
+                        pos.key,
+                        pos.generate_zobrist_key(),
+                        "{} in {}",
+                        mv,
+                        before.to_fen()
+                    );
+                    // Unhashed moves differ only in the key
+                    let mut unhashed = before.make_move_unhashed(mv);
+                    unhashed.key = pos.key;
+                    assert_eq!(unhashed, pos, "{} in {}", mv, before.to_fen());
+                    pos.undo_move(mv, undo);
+                    assert_eq!(pos, before, "{} in {}", mv, before.to_fen());
+                }
+                let mv = moves[rng.next() as usize % moves.len()];
+                history.push((mv, pos.do_move(&mv)));
+            }
+
+            while let Some((mv, undo)) = history.pop() {
+                pos.undo_move(&mv, undo);
+            }
+            assert_eq!(pos, start);
+        }
     }
 }
@@ -2,31 +2,97 @@ use super::*;
 
 use std::cmp::Ordering;
 
-use movelist::MoveList;
+use constants::file::{FILE_C, FILE_D, FILE_F, FILE_G};
+
+use movelist::{MoveList, MoveVec};
+use mv::Move;
 use pieces::*;
 use position::states::*;
 use position::Position;
+use stages::*;
 use types::{ColorT, MoveT};
 
-/// Generate all legal moves in a position
+mod pseudo;
+pub mod stages;
+
+pub use pseudo::{generate_pseudo_legal, Backend, LegalFilter};
+
+impl Position {
+    /// Return an iterator over all legal moves in the position
+    ///
+    /// ```
+    /// use rperft::Position;
+    ///
+    /// let pos = Position::new_start_pos();
+    /// let moves: Vec<String> = pos.legal_moves().map(|mv| mv.to_algebraic()).collect();
+    /// assert!(moves.contains(&"g1f3".to_string()));
+    /// ```
+    pub fn legal_moves(&self) -> impl Iterator<Item = Move> {
+        let mut movelist = MoveVec::new();
+        generate_all(self, &mut movelist);
+        movelist.into_iter()
+    }
+}
+
+/// Generate all legal moves in a position, passing them to a movelist sink
+///
+/// ```
+/// use rperft::{generate_all, MoveCounter, Position};
+///
+/// let pos = Position::new_start_pos();
+/// let mut counter = MoveCounter::default();
+/// generate_all(&pos, &mut counter);
+/// assert_eq!(counter.nodes, 20);
+/// ```
 pub fn generate_all<M: MoveList>(pos: &Position, movelist: &mut M) {
     match pos.stm {
-        ColorT::White => generate_all_inner::<M, White>(pos, movelist),
-        ColorT::Black => generate_all_inner::<M, Black>(pos, movelist),
+        ColorT::White => generate_inner::<M, White, All>(pos, movelist),
+        ColorT::Black => generate_inner::<M, Black, All>(pos, movelist),
+    }
+}
+
+/// Generate the legal moves of a stage in a position, passing them to a
+/// movelist sink. [`Captures`] and [`Quiets`] split the legal moves in two
+///
+/// ```
+/// use rperft::stages::{Captures, Quiets};
+/// use rperft::{generate, MoveCounter, Position};
+///
+/// let pos = Position::from_fen(
+///     "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+/// ).unwrap();
+/// let mut captures = MoveCounter::default();
+/// generate::<Captures>(&pos, &mut captures);
+/// let mut quiets = MoveCounter::default();
+/// generate::<Quiets>(&pos, &mut quiets);
+/// assert_eq!((captures.nodes, quiets.nodes), (8, 40));
+/// ```
+pub fn generate<G: Stage>(pos: &Position, movelist: &mut impl MoveList) {
+    match pos.stm {
+        ColorT::White => generate_inner::<_, White, G>(pos, movelist),
+        ColorT::Black => generate_inner::<_, Black, G>(pos, movelist),
     }
 }
 
 #[inline(always)]
-fn generate_all_inner<M: MoveList, C: Color>(pos: &Position, movelist: &mut M) {
+fn generate_inner<M: MoveList, C: Color, G: Stage>(pos: &Position, movelist: &mut M) {
     let checkers = pos.checkers::<C>();
     let n_checkers = checkers.pop_count();
+    if G::EVASIONS && n_checkers == 0 {
+        return;
+    }
     let unsafe_sq = pos.unsafe_sq::<C>();
+    let discoverers = if G::CHECKS {
+        pos.discoverers()
+    } else {
+        constants::bb::EMPTY
+    };
     let filter;
 
     match n_checkers.cmp(&1) {
         Ordering::Greater => {
             // In double check, only king moves are valid
-            generate_king_moves::<M>(pos, movelist, unsafe_sq);
+            generate_king_moves::<M, G>(pos, movelist, unsafe_sq, discoverers);
             return;
         }
         Ordering::Equal => {
@@ -37,36 +103,67 @@ fn generate_all_inner<M: MoveList, C: Color>(pos: &Position, movelist: &mut M) {
             // Not in check so all squares that are not occupied by our pieces are valid targets
             filter = !pos.us.all;
             // Castling is allowed only when not in check
-            generate_castles::<M, C>(pos, movelist, unsafe_sq);
+            if G::QUIETS {
+                generate_castles::<M, C, G>(pos, movelist, unsafe_sq);
+            }
         }
     }
 
     let pinned = pos.pinned();
 
-    generate_moves::<M, Rook>(pos, movelist, pinned, filter);
-    generate_moves::<M, Knight>(pos, movelist, pinned, filter);
-    generate_moves::<M, Bishop>(pos, movelist, pinned, filter);
-    generate_moves::<M, Queen>(pos, movelist, pinned, filter);
+    // Pieces other than pawns never promote, so their stage only selects
+    // between captures and quiet moves
+    let mut targets = filter;
+    if !G::CAPTURES {
+        targets &= pos.free;
+    }
+    if !G::QUIETS {
+        targets &= pos.occ;
+    }
+
+    generate_moves::<M, Rook, G>(pos, movelist, pinned, targets, discoverers);
+    generate_moves::<M, Knight, G>(pos, movelist, pinned, targets, discoverers);
+    generate_moves::<M, Bishop, G>(pos, movelist, pinned, targets, discoverers);
+    generate_moves::<M, Queen, G>(pos, movelist, pinned, targets, discoverers);
 
-    generate_pawn_moves::<M, C>(pos, movelist, pinned, filter);
-    generate_king_moves::<M>(pos, movelist, unsafe_sq);
+    generate_pawn_moves::<M, C, G, true>(pos, movelist, pinned, filter, discoverers);
+    generate_king_moves::<M, G>(pos, movelist, unsafe_sq, discoverers);
 }
 
 #[inline(always)]
-fn generate_king_moves<M: MoveList>(pos: &Position, movelist: &mut M, unsafe_sq: BitBoard) {
+fn generate_king_moves<M: MoveList, G: Stage>(
+    pos: &Position,
+    movelist: &mut M,
+    unsafe_sq: BitBoard,
+    discoverers: BitBoard,
+) {
     let from = pos.us.king;
-    let targets = from.king_attacks_lu() & !pos.us.all & !unsafe_sq;
+    let mut targets = from.king_attacks_lu() & !pos.us.all & !unsafe_sq;
+    // The king can only give check by uncovering an attack
+    if G::CHECKS {
+        if (from & discoverers).is_empty() {
+            return;
+        }
+        targets &= !pos.them.king.between_mask(from);
+    }
     let quiet_targets = targets & pos.free;
-    movelist.add_quiets(from, targets & quiet_targets);
-    movelist.add_captures(from, targets ^ quiet_targets);
+    if G::QUIETS {
+        movelist.add_quiets(from, targets & quiet_targets);
+    }
+    if G::CAPTURES {
+        movelist.add_captures(from, targets ^ quiet_targets);
+    }
 }
 
+/// Unless `LEGAL`, en passant captures revealing a check along the rank are
+/// also generated
 #[inline(always)]
-fn generate_pawn_moves<M: MoveList, C: Color>(
+fn generate_pawn_moves<M: MoveList, C: Color, G: Stage, const LEGAL: bool>(
     pos: &Position,
     movelist: &mut M,
     pinned: BitBoard,
     filter: BitBoard,
+    discoverers: BitBoard,
 ) {
     // Filter pawns according to if they are pinned and the pin direction
     let pinned = pos.us.pawn & pinned;
@@ -89,14 +186,29 @@ fn generate_pawn_moves<M: MoveList, C: Color>(
     let not_on_7 = pawns ^ on_7;
 
     // Add single and double pushes
-    let mut bb_1 = C::push_one(not_on_7 & !no_push) & pos.free;
-    let mut bb_2 = C::push_one(bb_1 & C::rank_3()) & pos.free;
+    if G::QUIETS {
+        let mut bb_1 = C::push_one(not_on_7 & !no_push) & pos.free;
+        let mut bb_2 = C::push_one(bb_1 & C::rank_3()) & pos.free;
+
+        bb_1 &= filter;
+        bb_2 &= filter;
+
+        // Pushes give check by attacking the king, or by uncovering an
+        // attack along a rank or diagonal
+        if G::CHECKS {
+            let checks = C::l_cap_back(pos.them.king) | C::r_cap_back(pos.them.king);
+            let uncovering = discoverers & pos.us.pawn & !pos.them.king.file_mask_lu();
+            bb_1 &= checks | C::push_one(uncovering);
+            bb_2 &= checks | C::push_one(C::push_one(uncovering));
+        }
 
-    bb_1 &= filter;
-    bb_2 &= filter;
+        movelist.add_pawn_pushes(C::back_one(bb_1), bb_1);
+        movelist.add_double_pawn_pushes(C::back_two(bb_2), bb_2);
+    }
 
-    movelist.add_pawn_pushes(C::back_one(bb_1), bb_1);
-    movelist.add_double_pawn_pushes(C::back_two(bb_2), bb_2);
+    if !G::CAPTURES {
+        return;
+    }
 
     // Add promotions
     let bb_1 = C::push_one(on_7 & !no_push) & pos.free & filter;
@@ -130,7 +242,7 @@ fn generate_pawn_moves<M: MoveList, C: Color>(
 
     for from in s_1 | s_2 {
         // Check rare case where an ep can reveal a discovered check along the 5th rank
-        if (pos.us.king & C::rank_5()).is_empty() {
+        if !LEGAL || (pos.us.king & C::rank_5()).is_empty() {
             movelist.add_ep(from, pos.ep_sq);
             continue;
         }
@@ -145,39 +257,96 @@ fn generate_pawn_moves<M: MoveList, C: Color>(
 }
 
 #[inline(always)]
-fn generate_moves<M: MoveList, P: Piece>(
+fn generate_moves<M: MoveList, P: Piece, G: Stage>(
     pos: &Position,
     movelist: &mut M,
     pinned: BitBoard,
     filter: BitBoard,
+    discoverers: BitBoard,
 ) {
+    // Squares from which the piece would attack the opponent king
+    let checks = P::generate_attacks(pos.them.king, pos.occ);
     for from in pos.us[P::pt()] {
         let mut targets = P::generate_attacks(from, pos.occ) & filter;
         // For pinned pieces, allow only moves towards or away from king
         if (from & pinned).is_not_empty() {
             targets &= pos.us.king.between_mask(from)
         }
+        // Checks attack the king directly, or uncover an attack by leaving
+        // the line between the king and one of our sliding pieces
+        if G::CHECKS {
+            let mut checking = checks;
+            if (from & discoverers).is_not_empty() {
+                checking |= !pos.them.king.between_mask(from);
+            }
+            targets &= checking;
+        }
         movelist.add_quiets(from, targets & pos.free);
         movelist.add_captures(from, targets & pos.occ);
     }
 }
 
 #[inline(always)]
-fn generate_castles<M: MoveList, C: Color>(pos: &Position, movelist: &mut M, unsafe_sq: BitBoard) {
-    let from = pos.us.king;
+fn generate_castles<M: MoveList, C: Color, G: Stage>(
+    pos: &Position,
+    movelist: &mut M,
+    unsafe_sq: BitBoard,
+) {
+    let king = pos.us.king;
+    if (king & C::rank_1()).is_empty() {
+        return;
+    }
 
-    if (pos.castling_rights & C::ksr_start_sq()).is_not_empty()
-        && (C::ksc_mask() & pos.occ).is_empty()
-        && (C::ksc_mask() & unsafe_sq).is_empty()
-    {
-        movelist.add_castle(from, from.east_two(), MoveT::KSCastle);
+    // Castling rights are stored as the squares of the castling rooks
+    for rook in pos.castling_rights & C::rank_1() & pos.us.rook {
+        let (mt, king_to, rook_to) = if rook.0 > king.0 {
+            (MoveT::KSCastle, C::rank_1() & FILE_G, C::rank_1() & FILE_F)
+        } else {
+            (MoveT::QSCastle, C::rank_1() & FILE_C, C::rank_1() & FILE_D)
+        };
+
+        // All squares the king and rook travel to must be free, other than
+        // those occupied by the castling king and rook themselves, and the
+        // king cannot pass through an attacked square
+        let king_path = castle_path(king, king_to);
+        let rook_path = castle_path(rook, rook_to);
+        if ((king_path | rook_path) & pos.occ & !(king | rook)).is_not_empty()
+            || (king_path & unsafe_sq).is_not_empty()
+        {
+            continue;
+        }
+
+        // In Chess960, the castling rook may be shielding the king's target
+        // square from an attack along the back rank
+        if pos.chess960 {
+            let occ = (pos.occ ^ king ^ rook) | king_to | rook_to;
+            if (king_to.hq_rank_attacks(occ) & (pos.them.rook | pos.them.queen)).is_not_empty() {
+                continue;
+            }
+        }
+
+        // Encode Chess960 castles as the king capturing its own rook
+        let to = if pos.chess960 { rook } else { king_to };
+
+        // Castles rarely give check, so the move is made to find out
+        if G::CHECKS {
+            let new_pos = pos.make_move_unhashed(&Move::encode(king, to, mt));
+            if new_pos.stm_checkers().is_empty() {
+                continue;
+            }
+        }
+        movelist.add_castle(king, to, mt);
     }
+}
 
-    if (pos.castling_rights & C::qsr_start_sq()).is_not_empty()
-        && (C::qsc_free_mask() & pos.occ).is_empty()
-        && (C::qsc_safety_mask() & unsafe_sq).is_empty()
-    {
-        movelist.add_castle(from, from.west_two(), MoveT::QSCastle);
+/// Return the squares travelled through by a castling piece, including the
+/// target square
+#[inline(always)]
+fn castle_path(from: BitBoard, to: BitBoard) -> BitBoard {
+    if from == to {
+        constants::bb::EMPTY
+    } else {
+        from.between_bb(to)
     }
 }
 
@@ -239,7 +408,6 @@ mod tests {
     use test_case::test_case;
 
     use constants::fen::*;
-    use movelist::MoveVec;
 
     struct Expected {
         count: usize,
@@ -0,0 +1,301 @@
+/// Pseudo-legal move generation, with a filter which removes the moves that
+/// leave the king in check
+use super::*;
+
+use std::iter::zip;
+
+use constants::bb::EMPTY;
+
+/// A move generator, which can be chosen at runtime
+///
+/// ```
+/// use rperft::{Backend, MoveCounter, Position};
+///
+/// let pos = Position::new_start_pos();
+/// let mut counter = MoveCounter::default();
+/// Backend::PseudoLegal.generate(&pos, &mut counter);
+/// assert_eq!(counter.nodes, 20);
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Generate legal moves directly from the pinned pieces and checkers
+    #[default]
+    Legal,
+    /// Generate pseudo-legal moves and test each one for legality
+    PseudoLegal,
+}
+
+impl Backend {
+    /// Generate all legal moves in a position with this backend
+    pub fn generate<M: MoveList>(self, pos: &Position, movelist: &mut M) {
+        match self {
+            Self::Legal => generate_all(pos, movelist),
+            Self::PseudoLegal => generate_pseudo_legal(pos, &mut LegalFilter::new(pos, movelist)),
+        }
+    }
+}
+
+/// Generate all pseudo-legal moves in a position, which may leave the king
+/// in check. Castles are generated whenever the path is free, even in check
+/// or through attacked squares
+pub fn generate_pseudo_legal<M: MoveList>(pos: &Position, movelist: &mut M) {
+    match pos.stm {
+        ColorT::White => generate_pseudo_legal_inner::<M, White>(pos, movelist),
+        ColorT::Black => generate_pseudo_legal_inner::<M, Black>(pos, movelist),
+    }
+}
+
+#[inline(always)]
+fn generate_pseudo_legal_inner<M: MoveList, C: Color>(pos: &Position, movelist: &mut M) {
+    // Without pins, checks or attacked squares, every square which is not
+    // occupied by our pieces is a target
+    let filter = !pos.us.all;
+
+    generate_castles::<M, C, All>(pos, movelist, EMPTY);
+
+    generate_moves::<M, Rook, All>(pos, movelist, EMPTY, filter, EMPTY);
+    generate_moves::<M, Knight, All>(pos, movelist, EMPTY, filter, EMPTY);
+    generate_moves::<M, Bishop, All>(pos, movelist, EMPTY, filter, EMPTY);
+    generate_moves::<M, Queen, All>(pos, movelist, EMPTY, filter, EMPTY);
+
+    generate_pawn_moves::<M, C, All, false>(pos, movelist, EMPTY, filter, EMPTY);
+    generate_king_moves::<M, All>(pos, movelist, EMPTY, EMPTY);
+}
+
+impl Position {
+    /// Return true if a pseudo-legal move does not leave the king in check.
+    /// Castles must also not start in check or pass through attacked squares
+    ///
+    /// ```
+    /// use rperft::Position;
+    ///
+    /// let pos = Position::new_start_pos();
+    /// assert!(pos.legal_moves().all(|mv| pos.is_legal(&mv)));
+    /// ```
+    pub fn is_legal(&self, mv: &Move) -> bool {
+        match self.stm {
+            ColorT::White => self.is_legal_inner::<White>(mv),
+            ColorT::Black => self.is_legal_inner::<Black>(mv),
+        }
+    }
+
+    #[inline(always)]
+    fn is_legal_inner<C: Color>(&self, mv: &Move) -> bool {
+        if let MoveT::KSCastle | MoveT::QSCastle = mv.mt() {
+            let (king_to, _, _) = self.castle_squares::<C>(mv);
+            let king_path = self.us.king | castle_path(self.us.king, king_to);
+            if (king_path & self.unsafe_sq::<C>()).is_not_empty() {
+                return false;
+            }
+        }
+        !self.make_move_unhashed(mv).opponent_in_check()
+    }
+}
+
+/// A movelist which passes only the legal moves it receives on to another
+/// movelist. Combined with [`generate_pseudo_legal`], this generates the same
+/// moves as [`generate_all`]
+///
+/// ```
+/// use rperft::{generate_pseudo_legal, LegalFilter, MoveCounter, Position};
+///
+/// let pos = Position::from_fen("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1").unwrap();
+/// let mut counter = MoveCounter::default();
+/// generate_pseudo_legal(&pos, &mut LegalFilter::new(&pos, &mut counter));
+/// assert_eq!((counter.nodes, counter.captures), (3, 1));
+/// ```
+pub struct LegalFilter<'a, M: MoveList> {
+    pos: &'a Position,
+    movelist: &'a mut M,
+}
+
+impl<'a, M: MoveList> LegalFilter<'a, M> {
+    pub fn new(pos: &'a Position, movelist: &'a mut M) -> Self {
+        Self { pos, movelist }
+    }
+
+    /// Return the targets of a piece which can be moved to legally
+    fn legal_targets(&self, src: BitBoard, targets: BitBoard, mt: MoveT) -> BitBoard {
+        targets
+            .filter(|&to| self.pos.is_legal(&Move::encode(src, to, mt)))
+            .fold(EMPTY, |legal, to| legal | to)
+    }
+
+    /// Return the paired sources and targets of the legal pawn moves. Pairs
+    /// keep their order, so the n-th source still moves to the n-th target
+    fn legal_pairs(&self, srcs: BitBoard, targets: BitBoard, mt: MoveT) -> (BitBoard, BitBoard) {
+        zip(srcs, targets)
+            .filter(|&(from, to)| self.pos.is_legal(&Move::encode(from, to, mt)))
+            .fold((EMPTY, EMPTY), |(s, t), (from, to)| (s | from, t | to))
+    }
+}
+
+impl<M: MoveList> MoveList for LegalFilter<'_, M> {
+    fn add_quiets(&mut self, src: BitBoard, targets: BitBoard) {
+        let targets = self.legal_targets(src, targets, MoveT::Quiet);
+        self.movelist.add_quiets(src, targets);
+    }
+
+    fn add_captures(&mut self, src: BitBoard, targets: BitBoard) {
+        let targets = self.legal_targets(src, targets, MoveT::Capture);
+        self.movelist.add_captures(src, targets);
+    }
+
+    fn add_pawn_pushes(&mut self, srcs: BitBoard, targets: BitBoard) {
+        let (srcs, targets) = self.legal_pairs(srcs, targets, MoveT::Quiet);
+        self.movelist.add_pawn_pushes(srcs, targets);
+    }
+
+    fn add_double_pawn_pushes(&mut self, srcs: BitBoard, targets: BitBoard) {
+        let (srcs, targets) = self.legal_pairs(srcs, targets, MoveT::DoublePawnPush);
+        self.movelist.add_double_pawn_pushes(srcs, targets);
+    }
+
+    fn add_pawn_captures(&mut self, srcs: BitBoard, targets: BitBoard) {
+        let (srcs, targets) = self.legal_pairs(srcs, targets, MoveT::Capture);
+        self.movelist.add_pawn_captures(srcs, targets);
+    }
+
+    fn add_ep(&mut self, from: BitBoard, to: BitBoard) {
+        if self.pos.is_legal(&Move::encode(from, to, MoveT::EnPassant)) {
+            self.movelist.add_ep(from, to);
+        }
+    }
+
+    fn add_castle(&mut self, from: BitBoard, to: BitBoard, mt: MoveT) {
+        if self.pos.is_legal(&Move::encode(from, to, mt)) {
+            self.movelist.add_castle(from, to, mt);
+        }
+    }
+
+    // The promoted piece does not affect legality, so test one of them
+
+    fn add_promos(&mut self, srcs: BitBoard, targets: BitBoard) {
+        let (srcs, targets) = self.legal_pairs(srcs, targets, MoveT::QPromo);
+        self.movelist.add_promos(srcs, targets);
+    }
+
+    fn add_promo_captures(&mut self, srcs: BitBoard, targets: BitBoard) {
+        let (srcs, targets) = self.legal_pairs(srcs, targets, MoveT::QPromoCapture);
+        self.movelist.add_promo_captures(srcs, targets);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    use constants::fen::*;
+
+    /// Xorshift generator, to play reproducible random games
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    fn sorted_moves(pos: &Position, backend: Backend) -> Vec<u16> {
+        let mut moves = MoveVec::new();
+        backend.generate(pos, &mut moves);
+        let mut moves: Vec<u16> = moves.iter().map(|mv| mv.as_u16()).collect();
+        moves.sort_unstable();
+        moves
+    }
+
+    fn perft(pos: &Position, depth: u8, backend: Backend) -> u64 {
+        let mut moves = MoveVec::new();
+        backend.generate(pos, &mut moves);
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .iter()
+            .map(|mv| perft(&pos.make_move(mv), depth - 1, backend))
+            .sum()
+    }
+
+    // Both backends must generate the same moves, and count them the same
+    // through the bulk adders, in every position of many random games
+    #[test_case(STARTING_FEN; "startpos")]
+    #[test_case(TEST_2; "testpos2")]
+    #[test_case(TEST_3; "testpos3")]
+    #[test_case(TEST_4; "testpos4")]
+    #[test_case(TEST_5; "testpos5")]
+    #[test_case(TEST_6; "testpos6")]
+    #[test_case("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"; "chess960")]
+    #[test_case("7k/8/8/8/8/8/8/rR1K4 w B - 0 1"; "chess960_shielded")]
+    #[test_case("4k3/8/8/K2pP2q/8/8/8/8 w - d6 0 1"; "ep_rank_pin")]
+    #[test_case("8/8/8/2k5/3pP3/8/8/6BK b - e3 0 1"; "ep_along_pin")]
+    fn test_backends_agree(fen: &str) {
+        let start = Position::from_fen(fen).unwrap();
+        for seed in 1..=100u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9e3779b97f4a7c15));
+            let mut pos = start;
+            for _ in 0..200 {
+                let legal = sorted_moves(&pos, Backend::Legal);
+                let filtered = sorted_moves(&pos, Backend::PseudoLegal);
+                assert_eq!(legal, filtered, "{}", pos.to_fen());
+
+                let mut legal_count = MoveCounter::default();
+                let mut filtered_count = MoveCounter::default();
+                Backend::Legal.generate(&pos, &mut legal_count);
+                Backend::PseudoLegal.generate(&pos, &mut filtered_count);
+                assert_eq!(legal_count, filtered_count, "{}", pos.to_fen());
+
+                if legal.is_empty() {
+                    break;
+                }
+                let mv = Move::from_u16(legal[rng.next() as usize % legal.len()]);
+                pos = pos.make_move(&mv);
+            }
+        }
+    }
+
+    #[test_case(TEST_2, 3, 97862; "testpos2")]
+    #[test_case(TEST_3, 5, 674624; "testpos3")]
+    #[test_case(TEST_4, 3, 9467; "testpos4")]
+    #[test_case(TEST_5, 3, 62379; "testpos5")]
+    fn test_pseudo_legal_perft(fen: &str, depth: u8, nodes: u64) {
+        let pos = Position::from_fen(fen).unwrap();
+        assert_eq!(perft(&pos, depth, Backend::PseudoLegal), nodes);
+    }
+
+    #[test]
+    fn test_pseudo_legal_superset() {
+        // The king is in check from the rook, so only king moves and the
+        // capture of the rook are legal
+        let pos = Position::from_fen("4k3/8/8/8/8/8/4r3/R3K3 w Q - 0 1").unwrap();
+        let mut pseudo = MoveVec::new();
+        generate_pseudo_legal(&pos, &mut pseudo);
+        let legal = sorted_moves(&pos, Backend::Legal);
+        assert!(pseudo.len() > legal.len());
+        assert!(legal
+            .iter()
+            .all(|mv| pseudo.iter().any(|p| p.as_u16() == *mv)));
+        assert!(pseudo
+            .iter()
+            .any(|mv| mv.mt() == MoveT::QSCastle && !pos.is_legal(mv)));
+    }
+
+    #[test]
+    fn test_pseudo_legal_ep_along_rank() {
+        // Capturing en passant would leave the king in check from the queen
+        // along the rank, which only the legality test rejects
+        let pos = Position::from_fen("4k3/8/8/K2pP2q/8/8/8/8 w - d6 0 1").unwrap();
+        let mut pseudo = MoveVec::new();
+        generate_pseudo_legal(&pos, &mut pseudo);
+        let ep = pseudo
+            .iter()
+            .find(|mv| mv.mt() == MoveT::EnPassant)
+            .unwrap();
+        assert!(!pos.is_legal(ep));
+    }
+}
@@ -0,0 +1,163 @@
+//! Stages of move generation, which select a subset of the legal moves
+
+/// A subset of the legal moves, generated by [`generate`](crate::generate)
+pub trait Stage {
+    /// Generate captures, including en passant, and promotions
+    const CAPTURES: bool;
+    /// Generate moves which neither capture nor promote, including castles
+    const QUIETS: bool;
+    /// Generate quiet moves only if they give check
+    const CHECKS: bool;
+    /// Generate moves only if the side to move is in check
+    const EVASIONS: bool;
+}
+
+/// All legal moves, as generated by [`generate_all`](crate::generate_all)
+pub struct All;
+
+/// Legal captures and promotions. Every promotion is generated, including
+/// promotions which do not capture
+pub struct Captures;
+
+/// Legal moves which neither capture nor promote, including castles
+pub struct Quiets;
+
+/// All legal moves when in check, otherwise no moves
+pub struct Evasions;
+
+/// Legal moves which neither capture nor promote, and give check, including
+/// castles which give check with the rook
+pub struct QuietChecks;
+
+impl Stage for All {
+    const CAPTURES: bool = true;
+    const QUIETS: bool = true;
+    const CHECKS: bool = false;
+    const EVASIONS: bool = false;
+}
+
+impl Stage for Captures {
+    const CAPTURES: bool = true;
+    const QUIETS: bool = false;
+    const CHECKS: bool = false;
+    const EVASIONS: bool = false;
+}
+
+impl Stage for Quiets {
+    const CAPTURES: bool = false;
+    const QUIETS: bool = true;
+    const CHECKS: bool = false;
+    const EVASIONS: bool = false;
+}
+
+impl Stage for Evasions {
+    const CAPTURES: bool = true;
+    const QUIETS: bool = true;
+    const CHECKS: bool = false;
+    const EVASIONS: bool = true;
+}
+
+impl Stage for QuietChecks {
+    const CAPTURES: bool = false;
+    const QUIETS: bool = true;
+    const CHECKS: bool = true;
+    const EVASIONS: bool = false;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    use crate::movegen::*;
+    use constants::fen::*;
+
+    fn sorted_moves<G: Stage>(pos: &Position) -> Vec<u16> {
+        let mut moves = MoveVec::new();
+        generate::<G>(pos, &mut moves);
+        let mut moves: Vec<u16> = moves.iter().map(|mv| mv.as_u16()).collect();
+        moves.sort_unstable();
+        moves
+    }
+
+    fn count<G: Stage>(pos: &Position) -> MoveCounter {
+        let mut count = MoveCounter::default();
+        generate::<G>(pos, &mut count);
+        count
+    }
+
+    fn check_stages(pos: &Position) {
+        let fen = pos.to_fen();
+        let mut all = MoveVec::new();
+        generate_all(pos, &mut all);
+        let mut all: Vec<u16> = all.iter().map(|mv| mv.as_u16()).collect();
+        all.sort_unstable();
+        assert_eq!(sorted_moves::<All>(pos), all, "{fen}");
+
+        // Captures and quiets split all moves in two
+        let captures = sorted_moves::<Captures>(pos);
+        let quiets = sorted_moves::<Quiets>(pos);
+        let mut union = [captures.clone(), quiets.clone()].concat();
+        union.sort_unstable();
+        assert_eq!(union, all, "{fen}");
+        assert!(captures
+            .iter()
+            .map(|&mv| Move::from_u16(mv))
+            .all(|mv| mv.is_capture() || mv.is_promo()));
+        assert_eq!(
+            count::<Captures>(pos) + count::<Quiets>(pos),
+            count::<All>(pos),
+            "{fen}"
+        );
+
+        let evasions = sorted_moves::<Evasions>(pos);
+        if pos.stm_checkers().is_empty() {
+            assert!(evasions.is_empty(), "{fen}");
+        } else {
+            assert_eq!(evasions, all, "{fen}");
+        }
+
+        let quiet_checks: Vec<u16> = quiets
+            .iter()
+            .copied()
+            .filter(|&mv| {
+                let mv = Move::from_u16(mv);
+                pos.make_move(&mv).stm_checkers().is_not_empty()
+            })
+            .collect();
+        assert_eq!(sorted_moves::<QuietChecks>(pos), quiet_checks, "{fen}");
+    }
+
+    fn walk(pos: &Position, depth: u8) {
+        check_stages(pos);
+        if depth == 0 {
+            return;
+        }
+        let mut moves = MoveVec::new();
+        generate_all(pos, &mut moves);
+        for mv in moves.iter() {
+            walk(&pos.make_move(mv), depth - 1);
+        }
+    }
+
+    // The stages are compared with all moves in every position a few plies
+    // from each start position
+    #[test_case(STARTING_FEN, 3; "startpos")]
+    #[test_case(TEST_2, 3; "testpos2")]
+    #[test_case(TEST_3, 4; "testpos3")]
+    #[test_case(TEST_4, 3; "testpos4")]
+    #[test_case(TEST_5, 3; "testpos5")]
+    #[test_case(TEST_6, 2; "testpos6")]
+    #[test_case("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", 2; "chess960")]
+    #[test_case("4k3/8/8/8/8/8/4K3/4R3 w - - 0 1", 3; "king_discovers")]
+    #[test_case("7k/8/8/8/3P4/8/1B6/K7 w - - 0 1", 3; "pawn_discovers_diagonal")]
+    #[test_case("8/8/8/R2P3k/8/8/8/K7 w - - 0 1", 3; "pawn_discovers_rank")]
+    #[test_case("4k3/8/8/8/8/8/4N3/4R1K1 w - - 0 1", 3; "knight_discovers")]
+    #[test_case("5k2/8/8/8/8/8/8/4K2R w K - 0 1", 2; "short_castle_checks")]
+    #[test_case("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", 2; "long_castle_checks")]
+    #[test_case("1r1k4/8/8/8/8/8/8/1R2K3 w B - 0 1", 2; "chess960_castle_checks")]
+    fn test_stages_partition_moves(fen: &str, depth: u8) {
+        walk(&Position::from_fen(fen).unwrap(), depth);
+    }
+}
@@ -3,9 +3,52 @@ use super::*;
 use std::iter::zip;
 use std::ops::{Add, AddAssign};
 
+use movegen::generate_all;
 use mv::Move;
-use types::MoveT;
-
+use position::states::{Black, White};
+use position::Position;
+use types::{ColorT, MoveT};
+
+/// A sink for the moves found by the move generator. Moves are passed in bulk
+/// as bitboards so that sinks which only count moves can avoid decoding them.
+///
+/// ```
+/// use rperft::{generate_all, BitBoard, MoveList, MoveT, Position};
+///
+/// /// Count only the moves which capture a piece
+/// #[derive(Default)]
+/// struct CaptureCounter(u32);
+///
+/// impl MoveList for CaptureCounter {
+///     fn add_quiets(&mut self, _src: BitBoard, _targets: BitBoard) {}
+///     fn add_captures(&mut self, _src: BitBoard, targets: BitBoard) {
+///         self.0 += targets.pop_count() as u32
+///     }
+///     fn add_pawn_pushes(&mut self, _srcs: BitBoard, _targets: BitBoard) {}
+///     fn add_double_pawn_pushes(&mut self, _srcs: BitBoard, _targets: BitBoard) {}
+///     fn add_pawn_captures(&mut self, _srcs: BitBoard, targets: BitBoard) {
+///         self.0 += targets.pop_count() as u32
+///     }
+///     fn add_ep(&mut self, _from: BitBoard, _to: BitBoard) {
+///         self.0 += 1
+///     }
+///     fn add_castle(&mut self, _from: BitBoard, _to: BitBoard, _mt: MoveT) {}
+///     fn add_promos(&mut self, _srcs: BitBoard, _targets: BitBoard) {}
+///     fn add_promo_captures(&mut self, _srcs: BitBoard, targets: BitBoard) {
+///         self.0 += targets.pop_count() as u32 * 4
+///     }
+/// }
+///
+/// let pos = Position::from_fen(
+///     "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+/// ).unwrap();
+/// let mut counter = CaptureCounter::default();
+/// generate_all(&pos, &mut counter);
+/// assert_eq!(counter.0, 8);
+/// ```
+///
+/// Pawn move adders receive the sources and targets as bitboards of equal
+/// population, where the n-th source bit moves to the n-th target bit.
 pub trait MoveList {
     // Piece move adders
     fn add_quiets(&mut self, src: BitBoard, targets: BitBoard);
@@ -21,6 +64,8 @@ pub trait MoveList {
     fn add_promo_captures(&mut self, srcs: BitBoard, targets: BitBoard);
 }
 
+/// A movelist which stores the moves
+#[derive(Debug, Default, Clone)]
 pub struct MoveVec(pub Vec<Move>);
 
 impl MoveList for MoveVec {
@@ -93,11 +138,24 @@ impl MoveVec {
         self.0.len()
     }
 
-    pub fn iter(&self) -> std::slice::Iter<Move> {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Move> {
         self.0.iter()
     }
 }
 
+impl IntoIterator for MoveVec {
+    type Item = Move;
+    type IntoIter = std::vec::IntoIter<Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
 impl std::ops::Index<usize> for MoveVec {
     type Output = Move;
 
@@ -106,13 +164,18 @@ impl std::ops::Index<usize> for MoveVec {
     }
 }
 
-#[derive(Debug, Default)]
+/// A movelist which only counts the moves
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
 pub struct MoveCounter {
     pub nodes: u64,
     pub captures: u32,
     pub ep: u32,
     pub castles: u32,
     pub promotions: u32,
+    pub checks: u32,
+    pub discovered_checks: u32,
+    pub double_checks: u32,
+    pub checkmates: u32,
 }
 
 impl MoveList for MoveCounter {
@@ -165,6 +228,57 @@ impl MoveList for MoveCounter {
     }
 }
 
+impl MoveCounter {
+    /// Count a single move
+    pub fn from_move(mv: &Move) -> Self {
+        let mt = mv.mt();
+        Self {
+            nodes: 1,
+            captures: mv.is_capture() as u32,
+            ep: matches!(mt, MoveT::EnPassant) as u32,
+            castles: matches!(mt, MoveT::KSCastle | MoveT::QSCastle) as u32,
+            promotions: mv.is_promo() as u32,
+            ..Default::default()
+        }
+    }
+
+    /// Count a single move played in a position, including any check or
+    /// checkmate it gives. A single check is discovered if the checking piece
+    /// is not on a square the move has just occupied. As in the reference
+    /// perft tables, double checks are not also counted as discovered
+    pub fn from_move_in(pos: &Position, mv: &Move) -> Self {
+        let mut count = Self::from_move(mv);
+        let new_pos = pos.make_move(mv);
+        let checkers = new_pos.stm_checkers();
+        if checkers.is_empty() {
+            return count;
+        }
+
+        // The squares the move has just occupied, which for castles are the
+        // targets of both the king and the rook. In Chess960, either may land
+        // on a square the other has just left
+        let occupied = match mv.mt() {
+            MoveT::KSCastle | MoveT::QSCastle => {
+                let (king_to, _, rook_to) = match pos.stm {
+                    ColorT::White => pos.castle_squares::<White>(mv),
+                    ColorT::Black => pos.castle_squares::<Black>(mv),
+                };
+                king_to | rook_to
+            }
+            _ => mv.to(),
+        };
+        let mut replies = MoveCounter::default();
+        generate_all(&new_pos, &mut replies);
+
+        let double_check = checkers.pop_count() > 1;
+        count.checks = 1;
+        count.discovered_checks = (!double_check && (checkers & !occupied).is_not_empty()) as u32;
+        count.double_checks = double_check as u32;
+        count.checkmates = (replies.nodes == 0) as u32;
+        count
+    }
+}
+
 impl AddAssign for MoveCounter {
     fn add_assign(&mut self, rhs: Self) {
         self.nodes += rhs.nodes;
@@ -172,6 +286,10 @@ impl AddAssign for MoveCounter {
         self.ep += rhs.ep;
         self.castles += rhs.castles;
         self.promotions += rhs.promotions;
+        self.checks += rhs.checks;
+        self.discovered_checks += rhs.discovered_checks;
+        self.double_checks += rhs.double_checks;
+        self.checkmates += rhs.checkmates;
     }
 }
 
@@ -185,6 +303,10 @@ impl Add for MoveCounter {
             ep: self.ep + rhs.ep,
             castles: self.castles + rhs.castles,
             promotions: self.promotions + rhs.promotions,
+            checks: self.checks + rhs.checks,
+            discovered_checks: self.discovered_checks + rhs.discovered_checks,
+            double_checks: self.double_checks + rhs.double_checks,
+            checkmates: self.checkmates + rhs.checkmates,
         }
     }
 }
@@ -29,14 +29,31 @@ use types::{MoveT, PieceT};
     1xxx - promotion flag
 */
 
-#[derive(Clone, Copy)]
-pub struct Move(pub u16);
+/// A move encoded in 16 bits
+///
+/// ```
+/// use rperft::{MoveT, Position};
+///
+/// let pos = Position::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+/// let mv = pos.legal_moves().find(|mv| mv.to_algebraic() == "b7b8q").unwrap();
+/// assert_eq!(mv.from().to_algebraic(), "b7");
+/// assert_eq!(mv.to().to_algebraic(), "b8");
+/// assert_eq!(mv.mt(), MoveT::QPromo);
+/// assert!(mv.is_promo() && !mv.is_capture());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Move(pub(crate) u16);
 
 impl Move {
     pub fn null() -> Move {
         return Move(0);
     }
 
+    /// The raw 16 bit encoding of the move
+    pub fn as_u16(&self) -> u16 {
+        self.0
+    }
+
     pub fn from_u16(word: u16) -> Move {
         return Move(word);
     }
@@ -85,6 +102,7 @@ impl Move {
         MAP[((self.0 & 0x3000) >> 12) as usize]
     }
 
+    /// Encode the move in long algebraic (UCI) notation e.g. e7e8q
     pub fn to_algebraic(&self) -> String {
         let from = self.from().to_algebraic();
         let to = self.to().to_algebraic();
@@ -105,8 +123,8 @@ impl Move {
     }
 }
 
-impl std::cmp::PartialEq for Move {
-    fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+impl std::fmt::Display for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_algebraic())
     }
 }
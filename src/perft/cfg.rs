@@ -1,13 +1,95 @@
 use super::*;
 
+use std::path::PathBuf;
+
+use clap::ValueEnum;
 use prettytable::Table;
+use serde::Serialize;
+
+use cache::Replacement;
+
+/// How a run is spread over threads
+#[derive(Debug, Clone)]
+pub struct ThreadOptions {
+    pub multithreading: bool,
+    /// Number of worker threads, one per core if not given
+    pub threads: Option<usize>,
+    /// Plies below the root at which the tree is split into jobs
+    pub split_depth: u8,
+    /// Pin each worker thread to its own core
+    pub pin: bool,
+}
+
+impl Default for ThreadOptions {
+    fn default() -> Self {
+        Self {
+            multithreading: true,
+            threads: None,
+            split_depth: constants::DEFAULT_SPLIT_DEPTH,
+            pin: false,
+        }
+    }
+}
 
+/// How the cache is set up for a run
+#[derive(Debug, Clone)]
+pub struct CacheOptions {
+    /// Size of the cache in bytes, 0 disables caching
+    pub size: usize,
+    /// File keeping the cache between runs
+    pub file: Option<PathBuf>,
+    pub replacement: Replacement,
+    /// Checksum entries and recompute a sample of shallow hits
+    pub verify: bool,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            size: constants::DEFAULT_CACHE_SIZE,
+            file: None,
+            replacement: Replacement::default(),
+            verify: false,
+        }
+    }
+}
+
+/// Parse a cache size in bytes, or with a K, M or G suffix for binary
+/// multiples, e.g. `512M` or `4G`
+pub fn parse_cache_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let digits = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let shift = match s[digits.len()..].to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 10,
+        "M" | "MB" | "MIB" => 20,
+        "G" | "GB" | "GIB" => 30,
+        "T" | "TB" | "TIB" => 40,
+        _ => return Err(format!("unknown unit in '{s}', expected K, M, G or T")),
+    };
+    let n: usize = digits
+        .parse()
+        .map_err(|_| format!("'{s}' is not a size, e.g. 32000000, 512M or 4G"))?;
+    // A terabyte does not fit in the usize of 32 bit targets
+    1usize
+        .checked_shl(shift)
+        .and_then(|unit| n.checked_mul(unit))
+        .ok_or_else(|| format!("'{s}' is too large"))
+}
+
+#[derive(Serialize)]
 pub struct Config {
     pub multithreading: bool,
     pub caching: bool,
     pub num_threads: usize,
+    pub split_depth: u8,
+    pub pin_threads: bool,
     pub cache_size: usize,
+    pub cache_file: Option<PathBuf>,
+    pub replacement: Replacement,
+    pub verify: bool,
     pub detailed: bool,
+    pub backend: Backend,
 }
 
 impl Config {
@@ -16,8 +98,37 @@ impl Config {
             multithreading,
             caching: cache_size > 0,
             num_threads: if multithreading { num_cpus::get() } else { 1 },
+            split_depth: constants::DEFAULT_SPLIT_DEPTH,
+            pin_threads: false,
             cache_size,
+            cache_file: None,
+            replacement: Replacement::default(),
+            verify: false,
             detailed,
+            backend: Backend::default(),
+        }
+    }
+
+    pub fn with_options(
+        threads: &ThreadOptions,
+        cache: &CacheOptions,
+        detailed: bool,
+        backend: Backend,
+    ) -> Self {
+        let num_threads = match (threads.multithreading, threads.threads) {
+            (false, _) => 1,
+            (true, Some(n)) => n,
+            (true, None) => num_cpus::get(),
+        };
+        Self {
+            num_threads,
+            split_depth: threads.split_depth,
+            pin_threads: threads.pin && threads.multithreading,
+            cache_file: cache.file.clone(),
+            replacement: cache.replacement,
+            verify: cache.verify,
+            backend,
+            ..Self::new(threads.multithreading, cache.size, detailed)
         }
     }
 
@@ -26,22 +137,51 @@ impl Config {
         table.add_row(row![b->"feature", "enabled", "info"]);
 
         let m = if self.multithreading {
-            format!("{} cores", self.num_threads)
+            let pinned = if self.pin_threads { "; pinned" } else { "" };
+            format!(
+                "{} threads; split depth {}{pinned}",
+                self.num_threads, self.split_depth
+            )
         } else {
-            format!("-")
+            "-".to_string()
         };
 
         let c = if self.caching {
-            let cache_size_mb = self.cache_size as f64 / 1_000_000.0;
-            let n_entries = self.cache_size / 32;
-            format!("{:.2} Mb; {} entries", cache_size_mb, n_entries)
+            // The table is rounded down to a power of two of buckets
+            let (n_entries, allocated) = match (self.detailed, self.verify) {
+                (false, false) => capacity::<Entry2xU64>(self.cache_size),
+                (true, false) => capacity::<Entry6xU64>(self.cache_size),
+                (false, true) => capacity::<Checked<Entry2xU64>>(self.cache_size),
+                (true, true) => capacity::<Checked<Entry6xU64>>(self.cache_size),
+            };
+            let cache_size_mb = allocated as f64 / (1 << 20) as f64;
+            let replacement = self
+                .replacement
+                .to_possible_value()
+                .expect("no skipped variants");
+            let info = format!(
+                "{:.2} MiB; {} entries; {} replacement",
+                cache_size_mb,
+                n_entries,
+                replacement.get_name()
+            );
+            let info = match &self.cache_file {
+                Some(path) => format!("{}; {}", info, path.display()),
+                None => info,
+            };
+            if self.verify {
+                format!("{info}; verified")
+            } else {
+                info
+            }
         } else {
-            format!("-")
+            "-".to_string()
         };
 
         table.add_row(row![b->"multithreading", self.multithreading, m]);
         table.add_row(row![b->"cache", self.caching, c]);
         table.add_row(row![b->"detailed count", self.detailed]);
+        table.add_row(row![b->"pseudo-legal movegen", self.backend == Backend::PseudoLegal]);
         table
     }
 
@@ -50,8 +190,23 @@ impl Config {
             multithreading: true,
             caching: true,
             num_threads: num_cpus::get(),
+            split_depth: constants::DEFAULT_SPLIT_DEPTH,
+            pin_threads: false,
             cache_size: constants::DEFAULT_CACHE_SIZE,
+            cache_file: None,
+            replacement: Replacement::default(),
+            verify: false,
             detailed: false,
+            backend: Backend::default(),
         }
     }
 }
+
+/// The number of entries in a cache of the given size, and the bytes
+/// allocated for them
+fn capacity<T: SizedEntry>(cache_size: usize) -> (usize, usize) {
+    (
+        Cache::<T>::capacity(cache_size),
+        Cache::<T>::allocated_size(cache_size),
+    )
+}
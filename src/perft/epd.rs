@@ -0,0 +1,55 @@
+/// Parsing of EPD perft suites in the format `<fen> ;D1 20 ;D2 400 ...`
+use super::*;
+
+/// A position from a perft suite with its expected node count at each depth
+#[derive(Debug, PartialEq, Eq)]
+pub struct EpdEntry {
+    pub fen: String,
+    pub expected: Vec<(u8, u64)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EpdError {
+    /// The position could not be parsed
+    InvalidFen(FenError),
+    /// An operation which is not a depth and node count pair, such as `D1 20`
+    InvalidOperation(String),
+}
+
+impl std::fmt::Display for EpdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EpdError::InvalidFen(e) => write!(f, "invalid FEN: {e}"),
+            EpdError::InvalidOperation(op) => write!(f, "invalid operation '{op}'"),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+impl EpdEntry {
+    /// Parse a line of an EPD perft suite. The position may omit the move
+    /// clocks, as is usual for EPD
+    pub fn parse(line: &str) -> Result<Self, EpdError> {
+        let mut fields = line.split(';');
+        let mut fen = fields.next().unwrap_or_default().trim().to_string();
+        if fen.split_whitespace().count() == 4 {
+            fen.push_str(" 0 1");
+        }
+        if let Err(e) = Position::from_fen(&fen) {
+            return Err(EpdError::InvalidFen(e));
+        }
+
+        let mut expected = Vec::new();
+        for op in fields.map(str::trim).filter(|op| !op.is_empty()) {
+            let invalid = || EpdError::InvalidOperation(op.to_string());
+            let (depth, nodes) = op.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            let depth = depth.strip_prefix('D').ok_or_else(invalid)?;
+            let depth = depth.parse::<u8>().map_err(|_| invalid())?;
+            let nodes = nodes.trim().parse::<u64>().map_err(|_| invalid())?;
+            expected.push((depth, nodes));
+        }
+        expected.sort();
+        Ok(Self { fen, expected })
+    }
+}
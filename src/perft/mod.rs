@@ -3,74 +3,288 @@ use super::*;
 
 use std::cmp::Ordering;
 use std::iter::zip;
-use std::sync::{mpsc::channel, Arc};
+use std::sync::{mpsc::channel, Arc, Barrier};
+use std::thread::ThreadId;
+use std::time::Instant;
 
 use threadpool::ThreadPool;
 
 use cache::*;
 use cfg::Config;
-use movegen::generate_all;
+use epd::EpdEntry;
+use movegen::{generate_all, Backend};
 use movelist::*;
-use position::Position;
+use mv::Move;
+use output::Record;
+use position::{FenError, Position};
 use stats::*;
 
 mod cfg;
+mod epd;
+mod output;
+mod scaling;
 mod stats;
 
+pub use cfg::{parse_cache_size, CacheOptions, ThreadOptions};
+pub use output::OutputFormat;
+
 #[cfg(test)]
 mod tests;
 
+/// One in this many cache hits is recomputed in verification mode, selected
+/// by the key
+const VERIFY_SAMPLE_RATE: u64 = 64;
+
+/// Only cache hits up to this depth are recomputed in verification mode.
+/// Each recomputation runs without the cache, so verifying deeper hits could
+/// cost more than the whole run saved by caching
+const VERIFY_MAX_DEPTH: u8 = 4;
+
 pub fn perft_wrapper(
     fen: &str,
     depth: u8,
-    cache_size: usize,
-    multithreading: bool,
+    cache: &CacheOptions,
+    threads: &ThreadOptions,
     detailed: bool,
+    backend: Backend,
+    output: OutputFormat,
 ) {
-    let cfg = Config::new(multithreading, cache_size, detailed);
-    let mut table = prettytable::Table::new();
-    table.add_row(Stats::start_row(&cfg));
+    let cfg = Config::with_options(threads, cache, detailed, backend);
 
     let pos = match Position::from_fen(fen) {
         Ok(p) => p,
-        Err(_) => {
-            log::error!("Invalid FEN: {fen}");
+        Err(e) => {
+            log::error!("Invalid FEN: {e}");
             return;
         }
     };
 
-    println!("{pos}");
-    cfg.report().printstd();
+    if output == OutputFormat::Table {
+        println!("{pos}");
+        cfg.report().printstd();
+    }
 
-    for d in 1..=depth {
-        let stats = if cfg.detailed {
-            perft::<Entry4xU64>(&pos, d, &cfg)
-        } else {
-            perft::<Entry2xU64>(&pos, d, &cfg)
-        };
-        table.add_row(stats.to_row(&cfg));
+    let runs: Vec<(Position, u8)> = (1..=depth).map(|d| (pos, d)).collect();
+    let results = match perft_runs_for(&runs, &cfg) {
+        Ok(r) => r,
+        Err(e) => {
+            log::error!("Could not use cache file: {e}");
+            return;
+        }
+    };
+
+    if output == OutputFormat::Table {
+        let mut table = prettytable::Table::new();
+        table.add_row(Stats::start_row(&cfg));
+        for stats in &results {
+            table.add_row(stats.to_row(&cfg));
+        }
+        println!();
+        table.printstd();
+        if let Some(deepest) = results.last().filter(|s| !s.thread_loads.is_empty()) {
+            println!();
+            deepest.load_table().printstd();
+        }
+        return;
     }
 
+    let records: Vec<Record> = results.iter().map(|stats| Record { fen, stats }).collect();
+    if let Err(e) = output::write_records(std::io::stdout(), &cfg, &records, output) {
+        log::error!("Could not write results: {e}");
+    }
+}
+
+/// Run perft on a position with 1, 2, 4, ... up to the configured number of
+/// threads, reporting the speedup and efficiency of each thread count. Only
+/// one thread is run if multithreading is off
+pub fn run_scaling_benchmark(
+    fen: &str,
+    depth: u8,
+    cache: &CacheOptions,
+    threads: &ThreadOptions,
+    detailed: bool,
+    backend: Backend,
+    output: OutputFormat,
+) {
+    let pos = match Position::from_fen(fen) {
+        Ok(p) => p,
+        Err(e) => {
+            log::error!("Invalid FEN: {e}");
+            return;
+        }
+    };
+    let max_threads = match (threads.multithreading, threads.threads) {
+        (false, _) => 1,
+        (true, Some(n)) => n,
+        (true, None) => num_cpus::get(),
+    };
+    let counts = scaling::thread_counts(max_threads);
+
+    if output == OutputFormat::Table {
+        println!("{pos}");
+        Config::with_options(threads, cache, detailed, backend)
+            .report()
+            .printstd();
+    }
+    let results = scaling::run_scaling(&pos, depth, cache, threads, detailed, backend, &counts);
+
+    if output == OutputFormat::Table {
+        println!();
+        scaling::scaling_table(&results).printstd();
+        return;
+    }
+    if let Err(e) = scaling::write_scaling(std::io::stdout(), &results, output) {
+        log::error!("Could not write results: {e}");
+    }
+}
+
+/// Count the nodes under each root move of a position, returning each move
+/// in UCI notation with its subtree count, sorted by the move
+pub fn perft_divide(
+    fen: &str,
+    depth: u8,
+    cache: &CacheOptions,
+    threads: &ThreadOptions,
+    detailed: bool,
+    backend: Backend,
+) -> Result<Vec<(String, MoveCounter)>, FenError> {
+    let cfg = Config::with_options(threads, cache, detailed, backend);
+    let pos = Position::from_fen(fen)?;
+    let counts = match (cfg.detailed, cfg.verify) {
+        (false, false) => divide::<Entry2xU64>(&pos, depth, &cfg),
+        (true, false) => divide::<Entry6xU64>(&pos, depth, &cfg),
+        (false, true) => divide::<Checked<Entry2xU64>>(&pos, depth, &cfg),
+        (true, true) => divide::<Checked<Entry6xU64>>(&pos, depth, &cfg),
+    };
+    Ok(counts
+        .into_iter()
+        .map(|(mv, count)| (mv.to_algebraic(), count))
+        .collect())
+}
+
+pub fn perft_divide_wrapper(
+    fen: &str,
+    depth: u8,
+    cache: &CacheOptions,
+    threads: &ThreadOptions,
+    detailed: bool,
+    backend: Backend,
+    output: OutputFormat,
+) {
+    let cfg = Config::with_options(threads, cache, detailed, backend);
+    let counts = match perft_divide(fen, depth, cache, threads, detailed, backend) {
+        Ok(c) => c,
+        Err(e) => {
+            log::error!("Invalid FEN: {e}");
+            return;
+        }
+    };
+
+    if output != OutputFormat::Table {
+        let result = output::write_divide(std::io::stdout(), &cfg, fen, depth, &counts, output);
+        if let Err(e) = result {
+            log::error!("Could not write results: {e}");
+        }
+        return;
+    }
+
+    let mut table = prettytable::Table::new();
+    table.add_row(Stats::divide_start_row(&cfg));
+    let mut total = MoveCounter::default();
+    for (mv, count) in counts {
+        table.add_row(Stats::divide_row(&mv, &count, &cfg));
+        total += count;
+    }
+    table.add_row(Stats::divide_row("total", &total, &cfg));
+
+    cfg.report().printstd();
     println!();
     table.printstd();
 }
 
+/// Speak the protocol of the perftree debugging tool: apply the space
+/// separated UCI moves to the position, then print each root move with its
+/// node count, followed by a blank line and the total
+pub fn perftree_wrapper(depth: u8, fen: &str, moves: Option<&str>) {
+    let pos = match Position::from_fen(fen) {
+        Ok(p) => p,
+        Err(e) => {
+            log::error!("Invalid FEN: {e}");
+            return;
+        }
+    };
+
+    let pos = match apply_uci_moves(&pos, moves.unwrap_or_default()) {
+        Ok(p) => p,
+        Err(uci) => {
+            log::error!("Illegal move: {uci}");
+            return;
+        }
+    };
+
+    let cfg = Config::new(true, constants::DEFAULT_CACHE_SIZE, false);
+    let mut total = 0;
+    for (mv, count) in divide::<Entry2xU64>(&pos, depth, &cfg) {
+        println!("{} {}", mv.to_algebraic(), count.nodes);
+        total += count.nodes;
+    }
+    println!();
+    println!("{total}");
+}
+
+/// Apply a sequence of space separated UCI moves to a position. Returns the
+/// first move which is not legal in the position it is played in as an error
+fn apply_uci_moves<'a>(pos: &Position, moves: &'a str) -> Result<Position, &'a str> {
+    let mut pos = *pos;
+    for uci in moves.split_whitespace() {
+        let mut legal_moves = MoveVec::new();
+        generate_all(&pos, &mut legal_moves);
+        match legal_moves.iter().find(|mv| mv.to_algebraic() == uci) {
+            Some(mv) => pos = pos.make_move(mv),
+            None => return Err(uci),
+        }
+    }
+    Ok(pos)
+}
+
 pub fn run_perft_benchmark_suite(
-    cache_size: usize,
-    multithreading: bool,
+    cache: &CacheOptions,
+    threads: &ThreadOptions,
     deep: bool,
     detailed: bool,
+    backend: Backend,
+    output: OutputFormat,
 ) {
     use constants::fen::*;
 
-    let cfg = cfg::Config::new(multithreading, cache_size, detailed);
+    let cfg = Config::with_options(threads, cache, detailed, backend);
 
     let tests = [STARTING_FEN, TEST_2, TEST_3, TEST_4, TEST_5, TEST_6];
-    let depths;
-    if deep {
-        depths = [7, 6, 8, 6, 6, 6]
+    let depths = if deep {
+        [7, 6, 8, 6, 6, 6]
     } else {
-        depths = [6, 5, 7, 5, 5, 5]
+        [6, 5, 7, 5, 5, 5]
+    };
+
+    let runs: Vec<(Position, u8)> = zip(tests, depths)
+        .map(|(fen, depth)| (Position::from_fen(fen).expect("valid fen"), depth))
+        .collect();
+    let results = match perft_runs_for(&runs, &cfg) {
+        Ok(r) => r,
+        Err(e) => {
+            log::error!("Could not use cache file: {e}");
+            return;
+        }
+    };
+
+    if output != OutputFormat::Table {
+        let records: Vec<Record> = zip(tests, &results)
+            .map(|(fen, stats)| Record { fen, stats })
+            .collect();
+        if let Err(e) = output::write_records(std::io::stdout(), &cfg, &records, output) {
+            log::error!("Could not write results: {e}");
+        }
+        return;
     }
 
     let mut table = prettytable::Table::new();
@@ -79,13 +293,7 @@ pub fn run_perft_benchmark_suite(
     start_row.insert_cell(0, cell!("Bench #"));
     table.add_row(start_row);
 
-    for (i, (fen, depth)) in zip(tests, depths).enumerate() {
-        let pos = Position::from_fen(fen).expect("valid fen");
-        let stats = if cfg.detailed {
-            perft::<Entry4xU64>(&pos, depth, &cfg)
-        } else {
-            perft::<Entry2xU64>(&pos, depth, &cfg)
-        };
+    for (i, stats) in results.iter().enumerate() {
         let mut row = stats.to_row(&cfg);
         row.insert_cell(0, cell!(i));
         table.add_row(row);
@@ -96,79 +304,444 @@ pub fn run_perft_benchmark_suite(
     table.printstd();
 }
 
-fn perft<T: SizedEntry + 'static>(pos: &Position, depth: u8, cfg: &cfg::Config) -> Stats {
-    let caching = cfg.cache_size > 0;
-    let num_threads;
+/// Run every position of an EPD perft suite to each listed depth, up to
+/// `max_depth` if given, printing whether each line matched its expected
+/// counts. Returns false on any mismatch or unreadable line
+pub fn run_epd_suite(
+    path: &str,
+    max_depth: Option<u8>,
+    cache: &CacheOptions,
+    threads: &ThreadOptions,
+    backend: Backend,
+) -> bool {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => {
+            log::error!("Could not read {path}: {e}");
+            return false;
+        }
+    };
 
-    if cfg.multithreading && depth > 3 {
-        num_threads = cfg.num_threads
+    let cfg = Config::with_options(threads, cache, false, backend);
+    if cfg.verify {
+        run_epd::<Checked<Entry2xU64>>(&contents, max_depth, &cfg)
     } else {
-        num_threads = 1
+        run_epd::<Entry2xU64>(&contents, max_depth, &cfg)
+    }
+}
+
+/// Run the lines of an EPD suite with cache entries of type `T`
+fn run_epd<T: SizedEntry + 'static>(contents: &str, max_depth: Option<u8>, cfg: &Config) -> bool {
+    let shared = match Shared::<T>::new(cfg) {
+        Ok(shared) => shared,
+        Err(e) => {
+            log::error!("Could not use cache file: {e}");
+            return false;
+        }
     };
+    cfg.report().printstd();
+    println!();
+
+    let (mut n_passed, mut n_failed) = (0, 0);
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_no = i + 1;
+        let entry = match EpdEntry::parse(line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                println!(
+                    "{line_no:>5} ERROR {line}
+      {e}"
+                );
+                n_failed += 1;
+                continue;
+            }
+        };
+
+        let pos = Position::from_fen(&entry.fen).expect("checked on parse");
+        let mut mismatches = Vec::new();
+        let mut n_nodes = 0;
+        let start = std::time::Instant::now();
+        for &(depth, expected) in &entry.expected {
+            if max_depth.is_some_and(|max| depth > max) {
+                continue;
+            }
+            let nodes = perft::<T>(&pos, depth, cfg, &shared).count.nodes;
+            if nodes != expected {
+                mismatches.push(format!("D{depth}: expected {expected}, found {nodes}"));
+            }
+            n_nodes += nodes;
+        }
+
+        let status = if mismatches.is_empty() {
+            "PASS"
+        } else {
+            "FAIL"
+        };
+        let duration = start.elapsed().as_secs_f64();
+        println!(
+            "{line_no:>5} {status}  {} ({n_nodes} nodes, {duration:.3} sec)",
+            entry.fen
+        );
+        for mismatch in &mismatches {
+            println!("      {mismatch}");
+        }
+        if mismatches.is_empty() {
+            n_passed += 1;
+        } else {
+            n_failed += 1;
+        }
+    }
+
+    println!();
+    println!("{n_passed} passed; {n_failed} failed");
+    n_failed == 0
+}
 
+/// The cache and thread pool shared by a series of perft runs, so entries
+/// from earlier runs are reused and the cache is only allocated once
+struct Shared<T: SizedEntry> {
+    cache: Arc<Cache<T>>,
+    pool: ThreadPool,
+}
+
+impl<T: SizedEntry> Shared<T> {
+    /// Allocate the cache, or open the cache file if one is configured
+    fn new(cfg: &Config) -> Result<Self, CacheFileError> {
+        let cache = match &cfg.cache_file {
+            Some(path) if cfg.caching => Cache::open(path, cfg.cache_size, cfg.replacement)?,
+            _ => Cache::new(cfg.cache_size, cfg.replacement, cfg.num_threads),
+        };
+        Ok(Self {
+            cache: Arc::new(cache),
+            pool: new_pool(cfg),
+        })
+    }
+
+    /// Allocate the cache in memory, ignoring any cache file
+    fn in_memory(cfg: &Config) -> Self {
+        Self {
+            cache: Arc::new(Cache::new(cfg.cache_size, cfg.replacement, cfg.num_threads)),
+            pool: new_pool(cfg),
+        }
+    }
+}
+
+/// Start the worker threads, pinned to cores if configured
+fn new_pool(cfg: &Config) -> ThreadPool {
+    let pool = ThreadPool::new(cfg.num_threads);
+    if !cfg.pin_threads {
+        return pool;
+    }
+    let Some(cores) = core_affinity::get_core_ids().filter(|cores| !cores.is_empty()) else {
+        log::warn!("Could not list the cores, worker threads are not pinned");
+        return pool;
+    };
+
+    // Each pinning job waits for the others, so every worker runs exactly one
+    let barrier = Arc::new(Barrier::new(cfg.num_threads));
+    for i in 0..cfg.num_threads {
+        let barrier = barrier.clone();
+        let core = cores[i % cores.len()];
+        pool.execute(move || {
+            barrier.wait();
+            if !core_affinity::set_for_current(core) {
+                log::warn!("Could not pin a worker thread to core {}", core.id);
+            }
+        });
+    }
+    pool.join();
+    pool
+}
+
+/// Run perft with the cache entry selected by the config
+fn perft_runs_for(runs: &[(Position, u8)], cfg: &Config) -> Result<Vec<Stats>, CacheFileError> {
+    match (cfg.detailed, cfg.verify) {
+        (false, false) => perft_runs::<Entry2xU64>(runs, cfg),
+        (true, false) => perft_runs::<Entry6xU64>(runs, cfg),
+        (false, true) => perft_runs::<Checked<Entry2xU64>>(runs, cfg),
+        (true, true) => perft_runs::<Checked<Entry6xU64>>(runs, cfg),
+    }
+}
+
+/// Run perft on each position to its depth in turn, sharing one cache and
+/// thread pool between the runs
+fn perft_runs<T: SizedEntry + 'static>(
+    runs: &[(Position, u8)],
+    cfg: &Config,
+) -> Result<Vec<Stats>, CacheFileError> {
+    let shared = Shared::<T>::new(cfg)?;
+    Ok(runs
+        .iter()
+        .map(|(pos, depth)| perft::<T>(pos, *depth, cfg, &shared))
+        .collect())
+}
+
+fn perft<T: SizedEntry + 'static>(
+    pos: &Position,
+    depth: u8,
+    cfg: &cfg::Config,
+    shared: &Shared<T>,
+) -> Stats {
     let mut stats = Stats::new(depth);
 
     match depth.cmp(&1) {
         Ordering::Less => stats.count.nodes += 1,
-        Ordering::Equal => generate_all(&pos, &mut stats.count),
+        Ordering::Equal => stats.count = count_moves(pos, cfg.detailed, cfg.backend),
+        Ordering::Greater if splits(depth, cfg) => {
+            let (counts, cache_stats, thread_loads) = perft_root::<T>(pos, depth, cfg, shared);
+            for (_, count) in counts {
+                stats.count += count;
+            }
+            stats.cache_stats += cache_stats;
+            stats.thread_loads = thread_loads;
+        }
         Ordering::Greater => {
             let mut moves = MoveVec::new();
-            generate_all(&pos, &mut moves);
-            let n_jobs = moves.len();
-            let pool = ThreadPool::new(num_threads);
-            let (tx, rx) = channel();
-            let cache = Arc::new(Cache::<T>::new(cfg.cache_size));
-
-            for i in 0..n_jobs {
-                let tx = tx.clone();
-                let mv = moves[i];
-                let new_pos = pos.make_move(&mv);
-                let cache = cache.clone();
-                pool.execute(move || {
-                    let mut cache_stats = CacheStats::default();
-                    let node_count = if caching {
-                        perft_inner_cache(&new_pos, depth - 1, &cache, &mut cache_stats)
+            cfg.backend.generate(pos, &mut moves);
+            for mv in moves.iter() {
+                let new_pos = pos.make_move(mv);
+                stats.count += if cfg.caching {
+                    let cache_stats = &mut stats.cache_stats;
+                    perft_inner_cache(
+                        &new_pos,
+                        depth - 1,
+                        cfg.detailed,
+                        cfg.backend,
+                        &shared.cache,
+                        cache_stats,
+                    )
+                } else {
+                    perft_inner(&new_pos, depth - 1, cfg.detailed, cfg.backend)
+                };
+            }
+        }
+    };
+    stats.end();
+    if cfg.caching {
+        stats.cache_fill = shared.cache.fill() * 100.0;
+    }
+    stats
+}
+
+/// Return true if a search is split into jobs for the thread pool. Each job
+/// must search at least two plies, otherwise the search runs on the calling
+/// thread
+fn splits(depth: u8, cfg: &cfg::Config) -> bool {
+    cfg.multithreading && depth > cfg.split_depth.saturating_add(1)
+}
+
+/// Split the search into jobs at the split depth below the root, returning
+/// the subtree count of each root move in generation order and the work done
+/// by each thread
+fn perft_root<T: SizedEntry + 'static>(
+    pos: &Position,
+    depth: u8,
+    cfg: &cfg::Config,
+    shared: &Shared<T>,
+) -> (Vec<(Move, MoveCounter)>, CacheStats, Vec<ThreadLoad>) {
+    debug_assert!(splits(depth, cfg));
+    let caching = cfg.caching;
+    let detailed = cfg.detailed;
+    let backend = cfg.backend;
+
+    let mut moves = MoveVec::new();
+    backend.generate(pos, &mut moves);
+    let mut counts: Vec<(Move, MoveCounter)> = moves
+        .iter()
+        .map(|mv| (*mv, MoveCounter::default()))
+        .collect();
+    let mut total_cache_stats = CacheStats::default();
+
+    // Positions at the split depth, with the root move leading to each
+    let split = cfg.split_depth.max(1);
+    let mut jobs = Vec::new();
+    for (i, mv) in moves.iter().enumerate() {
+        expand(&pos.make_move(mv), split - 1, i, backend, &mut jobs);
+    }
+
+    let n_jobs = jobs.len();
+    let (tx, rx) = channel();
+    for (i, job_pos) in jobs {
+        let tx = tx.clone();
+        let cache = shared.cache.clone();
+        shared.pool.execute(move || {
+            let start = Instant::now();
+            let (count, cache_stats) =
+                perft_subtree(&job_pos, depth - split, detailed, backend, caching, &cache);
+            let busy_sec = start.elapsed().as_secs_f64();
+            let thread = std::thread::current().id();
+            tx.send((i, count, cache_stats, thread, busy_sec)).unwrap()
+        })
+    }
+
+    let mut thread_loads: Vec<(ThreadId, ThreadLoad)> = Vec::new();
+    for (i, count, cache_stats, thread, busy_sec) in rx.iter().take(n_jobs) {
+        counts[i].1 += count;
+        total_cache_stats += cache_stats;
+
+        let index = match thread_loads.iter().position(|(id, _)| *id == thread) {
+            Some(index) => index,
+            None => {
+                thread_loads.push((thread, ThreadLoad::default()));
+                thread_loads.len() - 1
+            }
+        };
+        let load = &mut thread_loads[index].1;
+        load.jobs += 1;
+        load.nodes += count.nodes;
+        load.busy_sec += busy_sec;
+    }
+    // Wait for the jobs to release the cache, so a cache file can be closed
+    shared.pool.join();
+    let thread_loads = thread_loads.into_iter().map(|(_, load)| load).collect();
+    (counts, total_cache_stats, thread_loads)
+}
+
+/// Collect the positions `plies` below a position, each with the index of
+/// the root move it was reached by
+fn expand(
+    pos: &Position,
+    plies: u8,
+    root_move: usize,
+    backend: Backend,
+    jobs: &mut Vec<(usize, Position)>,
+) {
+    if plies == 0 {
+        jobs.push((root_move, *pos));
+        return;
+    }
+    let mut moves = MoveVec::new();
+    backend.generate(pos, &mut moves);
+    for mv in moves.iter() {
+        expand(&pos.make_move(mv), plies - 1, root_move, backend, jobs);
+    }
+}
+
+/// Count the subtree of a root move
+fn perft_subtree<T: SizedEntry>(
+    pos: &Position,
+    depth: u8,
+    detailed: bool,
+    backend: Backend,
+    caching: bool,
+    cache: &Arc<Cache<T>>,
+) -> (MoveCounter, CacheStats) {
+    let mut cache_stats = CacheStats::default();
+    let count = if caching {
+        perft_inner_cache(pos, depth, detailed, backend, cache, &mut cache_stats)
+    } else {
+        perft_inner(pos, depth, detailed, backend)
+    };
+    (count, cache_stats)
+}
+
+/// Count the nodes under each root move, sorted by the move in UCI notation
+fn divide<T: SizedEntry + 'static>(
+    pos: &Position,
+    depth: u8,
+    cfg: &cfg::Config,
+) -> Vec<(Move, MoveCounter)> {
+    let mut counts = match depth {
+        0 => Vec::new(),
+        1 => {
+            let mut moves = MoveVec::new();
+            cfg.backend.generate(pos, &mut moves);
+            moves
+                .iter()
+                .map(|mv| {
+                    let count = if cfg.detailed {
+                        MoveCounter::from_move_in(pos, mv)
                     } else {
-                        perft_inner(&new_pos, depth - 1)
+                        MoveCounter::from_move(mv)
                     };
-                    tx.send((node_count, cache_stats)).unwrap()
+                    (*mv, count)
                 })
+                .collect()
+        }
+        _ => {
+            let shared = Shared::<T>::in_memory(cfg);
+            if splits(depth, cfg) {
+                perft_root::<T>(pos, depth, cfg, &shared).0
+            } else {
+                let mut moves = MoveVec::new();
+                cfg.backend.generate(pos, &mut moves);
+                moves
+                    .iter()
+                    .map(|mv| {
+                        let new_pos = pos.make_move(mv);
+                        let (count, _) = perft_subtree(
+                            &new_pos,
+                            depth - 1,
+                            cfg.detailed,
+                            cfg.backend,
+                            cfg.caching,
+                            &shared.cache,
+                        );
+                        (*mv, count)
+                    })
+                    .collect()
             }
-
-            let (count, cache_stats) = rx
-                .iter()
-                .take(n_jobs)
-                .fold((MoveCounter::default(), CacheStats::default()), |a, b| {
-                    ((a.0 + b.0), (a.1 + b.1))
-                });
-            stats.count += count;
-            stats.cache_stats += cache_stats;
         }
     };
-    stats.end();
-    stats
+    counts.sort_by_key(|(mv, _)| mv.to_algebraic());
+    counts
+}
+
+/// Count the moves in a position. Detailed counts classify the checks given
+/// by each move, which requires every move to be made
+fn count_moves(pos: &Position, detailed: bool, backend: Backend) -> MoveCounter {
+    let mut count = MoveCounter::default();
+    if detailed {
+        let mut moves = MoveVec::new();
+        backend.generate(pos, &mut moves);
+        for mv in moves.iter() {
+            count += MoveCounter::from_move_in(pos, mv);
+        }
+    } else {
+        backend.generate(pos, &mut count);
+    }
+    count
+}
+
+/// Count the leaves two plies below a position. Each child is still made and
+/// its moves generated, but it is never cached, so its key is not updated
+fn count_depth_2(pos: &Position, backend: Backend) -> MoveCounter {
+    let mut moves = MoveVec::new();
+    backend.generate(pos, &mut moves);
+    let mut count = MoveCounter::default();
+    for mv in moves.iter() {
+        backend.generate(&pos.make_move_unhashed(mv), &mut count);
+    }
+    count
 }
 
-fn perft_inner(pos: &Position, depth: u8) -> MoveCounter {
+fn perft_inner(pos: &Position, depth: u8, detailed: bool, backend: Backend) -> MoveCounter {
     if depth == 1 {
-        let mut movelist = MoveCounter::default();
-        generate_all(pos, &mut movelist);
-        return movelist;
+        return count_moves(pos, detailed, backend);
+    }
+    if depth == 2 && !detailed {
+        return count_depth_2(pos, backend);
     }
 
     let mut movelist = MoveVec::new();
-    generate_all(pos, &mut movelist);
+    backend.generate(pos, &mut movelist);
     let mut count = MoveCounter::default();
     for mv in movelist.iter() {
         let new_pos = pos.make_move(mv);
-        count += perft_inner(&new_pos, depth - 1);
+        count += perft_inner(&new_pos, depth - 1, detailed, backend);
     }
-    return count;
+    count
 }
 
 fn perft_inner_cache<T: SizedEntry>(
     pos: &Position,
     depth: u8,
+    detailed: bool,
+    backend: Backend,
     cache: &Arc<Cache<T>>,
     stats: &mut CacheStats,
 ) -> MoveCounter {
@@ -177,24 +750,63 @@ fn perft_inner_cache<T: SizedEntry>(
         Access::Hit(count) => {
             stats.hits += 1;
             stats.hit_nodes += count.nodes;
+            if T::CHECKED
+                && depth <= VERIFY_MAX_DEPTH
+                && (pos.key >> 32).is_multiple_of(VERIFY_SAMPLE_RATE)
+            {
+                return verify_hit(pos, depth, detailed, backend, count, stats);
+            }
             return count;
         }
         Access::Miss => stats.misses += 1,
         Access::Collision => stats.collisions += 1,
+        Access::Torn => stats.torn += 1,
     }
 
     if depth == 1 {
+        return count_moves(pos, detailed, backend);
+    }
+    let count = if depth == 2 && !detailed {
+        count_depth_2(pos, backend)
+    } else {
+        let mut moves = MoveVec::new();
+        backend.generate(pos, &mut moves);
         let mut count = MoveCounter::default();
-        generate_all(pos, &mut count);
-        return count;
+        for mv in moves.iter() {
+            let new_position = pos.make_move(mv);
+            count += perft_inner_cache(&new_position, depth - 1, detailed, backend, cache, stats);
+        }
+        count
+    };
+    if cache.write(pos.key, depth, &count) {
+        stats.evictions += 1;
     }
-    let mut moves = MoveVec::new();
-    generate_all(&pos, &mut moves);
-    let mut count = MoveCounter::default();
-    for mv in moves.iter() {
-        let new_position = pos.make_move(mv);
-        count += perft_inner_cache(&new_position, depth - 1, cache, stats);
+    count
+}
+
+/// Recompute a cache hit without the cache, returning the correct counts
+fn verify_hit(
+    pos: &Position,
+    depth: u8,
+    detailed: bool,
+    backend: Backend,
+    cached: MoveCounter,
+    stats: &mut CacheStats,
+) -> MoveCounter {
+    let count = perft_inner(pos, depth, detailed, backend);
+    stats.verified += 1;
+    // Only node counts are cached unless counting in detail
+    let matches = if detailed {
+        count == cached
+    } else {
+        count.nodes == cached.nodes
+    };
+    if !matches {
+        stats.mismatches += 1;
+        log::warn!(
+            "Cached count at depth {depth} differs for {}: {cached:?}, expected {count:?}",
+            pos.to_fen()
+        );
     }
-    cache.write(pos.key, depth, &count);
-    return count;
+    count
 }
@@ -0,0 +1,215 @@
+/// Machine readable output of perft results
+use super::*;
+
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// Format in which results are written to stdout
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable tables
+    #[default]
+    Table,
+    /// A single JSON object with the config and a list of results
+    Json,
+    /// One row per result, with the config repeated on each row
+    Csv,
+}
+
+/// The statistics of a perft run of a position to a single depth
+#[derive(Serialize)]
+pub struct Record<'a> {
+    pub fen: &'a str,
+    #[serde(flatten)]
+    pub stats: &'a Stats,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    config: &'a Config,
+    results: &'a [Record<'a>],
+}
+
+/// The count under a root move of a divided position
+#[derive(Serialize)]
+struct DivideRecord<'a> {
+    #[serde(rename = "move")]
+    mv: &'a str,
+    #[serde(flatten)]
+    count: &'a MoveCounter,
+}
+
+#[derive(Serialize)]
+struct DivideReport<'a> {
+    config: &'a Config,
+    fen: &'a str,
+    depth: u8,
+    moves: Vec<DivideRecord<'a>>,
+    total: MoveCounter,
+}
+
+const CSV_HEADERS: [&str; 30] = [
+    "fen",
+    "depth",
+    "nodes",
+    "captures",
+    "ep",
+    "castles",
+    "promotions",
+    "checks",
+    "discovered_checks",
+    "double_checks",
+    "checkmates",
+    "duration_sec",
+    "m_nodes_per_sec",
+    "utilisation",
+    "hits",
+    "misses",
+    "collisions",
+    "evictions",
+    "torn",
+    "verified",
+    "mismatches",
+    "hit_nodes",
+    "cache_fill",
+    "multithreading",
+    "num_threads",
+    "cache_size",
+    "replacement",
+    "verify",
+    "detailed",
+    "backend",
+];
+
+/// Write the results of a run in a machine readable format
+pub fn write_records<W: Write>(
+    mut writer: W,
+    cfg: &Config,
+    records: &[Record],
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Table => unreachable!("tables are printed by the caller"),
+        OutputFormat::Json => {
+            let report = Report {
+                config: cfg,
+                results: records,
+            };
+            serde_json::to_writer_pretty(&mut writer, &report)?;
+            writeln!(writer)?;
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            writer.write_record(CSV_HEADERS)?;
+            for record in records {
+                let (stats, count) = (record.stats, &record.stats.count);
+                writer.write_record([
+                    record.fen.to_string(),
+                    stats.depth.to_string(),
+                    count.nodes.to_string(),
+                    count.captures.to_string(),
+                    count.ep.to_string(),
+                    count.castles.to_string(),
+                    count.promotions.to_string(),
+                    count.checks.to_string(),
+                    count.discovered_checks.to_string(),
+                    count.double_checks.to_string(),
+                    count.checkmates.to_string(),
+                    stats.duration_sec.to_string(),
+                    stats.m_nodes_per_sec.to_string(),
+                    stats
+                        .utilisation(cfg.num_threads)
+                        .map_or(String::new(), |u| u.to_string()),
+                    stats.cache_stats.hits.to_string(),
+                    stats.cache_stats.misses.to_string(),
+                    stats.cache_stats.collisions.to_string(),
+                    stats.cache_stats.evictions.to_string(),
+                    stats.cache_stats.torn.to_string(),
+                    stats.cache_stats.verified.to_string(),
+                    stats.cache_stats.mismatches.to_string(),
+                    stats.cache_stats.hit_nodes.to_string(),
+                    stats.cache_fill.to_string(),
+                    cfg.multithreading.to_string(),
+                    cfg.num_threads.to_string(),
+                    cfg.cache_size.to_string(),
+                    cfg.replacement
+                        .to_possible_value()
+                        .map_or(String::new(), |v| v.get_name().to_string()),
+                    cfg.verify.to_string(),
+                    cfg.detailed.to_string(),
+                    cfg.backend
+                        .to_possible_value()
+                        .map_or(String::new(), |v| v.get_name().to_string()),
+                ])?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+const DIVIDE_CSV_HEADERS: [&str; 10] = [
+    "move",
+    "nodes",
+    "captures",
+    "ep",
+    "castles",
+    "promotions",
+    "checks",
+    "discovered_checks",
+    "double_checks",
+    "checkmates",
+];
+
+/// Write the count under each root move in a machine readable format. JSON
+/// also includes the config and the total, CSV has one row per move
+pub fn write_divide<W: Write>(
+    mut writer: W,
+    cfg: &Config,
+    fen: &str,
+    depth: u8,
+    counts: &[(String, MoveCounter)],
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Table => unreachable!("tables are printed by the caller"),
+        OutputFormat::Json => {
+            let report = DivideReport {
+                config: cfg,
+                fen,
+                depth,
+                moves: counts
+                    .iter()
+                    .map(|(mv, count)| DivideRecord { mv, count })
+                    .collect(),
+                total: counts
+                    .iter()
+                    .fold(MoveCounter::default(), |total, (_, count)| total + *count),
+            };
+            serde_json::to_writer_pretty(&mut writer, &report)?;
+            writeln!(writer)?;
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            writer.write_record(DIVIDE_CSV_HEADERS)?;
+            for (mv, count) in counts {
+                writer.write_record([
+                    mv.clone(),
+                    count.nodes.to_string(),
+                    count.captures.to_string(),
+                    count.ep.to_string(),
+                    count.castles.to_string(),
+                    count.promotions.to_string(),
+                    count.checks.to_string(),
+                    count.discovered_checks.to_string(),
+                    count.double_checks.to_string(),
+                    count.checkmates.to_string(),
+                ])?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
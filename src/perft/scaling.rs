@@ -0,0 +1,121 @@
+/// Benchmark of how perft speed scales with the number of threads
+use super::*;
+
+use std::io::Write;
+
+use prettytable::Table;
+use serde::Serialize;
+
+/// The speed of a run with a number of threads
+#[derive(Debug, Serialize)]
+pub struct ScalingResult {
+    pub threads: usize,
+    pub nodes: u64,
+    pub duration_sec: f64,
+    pub m_nodes_per_sec: f64,
+    /// Speed relative to the run with one thread
+    pub speedup: f64,
+    /// Speedup per thread, 1 for perfect scaling
+    pub efficiency: f64,
+}
+
+/// Thread counts doubling from one, up to and including `max`
+pub fn thread_counts(max: usize) -> Vec<usize> {
+    let mut counts: Vec<usize> = std::iter::successors(Some(1), |n| Some(n * 2))
+        .take_while(|&n| n < max)
+        .collect();
+    counts.push(max.max(1));
+    counts
+}
+
+/// Run perft on the position once for each thread count. Every run starts
+/// with an empty cache, so that later runs do not profit from earlier ones
+pub fn run_scaling(
+    pos: &Position,
+    depth: u8,
+    cache: &CacheOptions,
+    threads: &ThreadOptions,
+    detailed: bool,
+    backend: Backend,
+    counts: &[usize],
+) -> Vec<ScalingResult> {
+    let mut results: Vec<ScalingResult> = Vec::with_capacity(counts.len());
+    for &n in counts {
+        let options = ThreadOptions {
+            multithreading: true,
+            threads: Some(n),
+            ..threads.clone()
+        };
+        let cfg = Config::with_options(&options, cache, detailed, backend);
+        let stats = match (cfg.detailed, cfg.verify) {
+            (false, false) => perft_in_memory::<Entry2xU64>(pos, depth, &cfg),
+            (true, false) => perft_in_memory::<Entry6xU64>(pos, depth, &cfg),
+            (false, true) => perft_in_memory::<Checked<Entry2xU64>>(pos, depth, &cfg),
+            (true, true) => perft_in_memory::<Checked<Entry6xU64>>(pos, depth, &cfg),
+        };
+
+        let base_sec = results
+            .first()
+            .map_or(stats.duration_sec, |r| r.duration_sec);
+        let speedup = base_sec / stats.duration_sec;
+        results.push(ScalingResult {
+            threads: n,
+            nodes: stats.count.nodes,
+            duration_sec: stats.duration_sec,
+            m_nodes_per_sec: stats.m_nodes_per_sec,
+            speedup,
+            efficiency: speedup / n as f64,
+        });
+    }
+    results
+}
+
+fn perft_in_memory<T: SizedEntry + 'static>(pos: &Position, depth: u8, cfg: &Config) -> Stats {
+    perft::<T>(pos, depth, cfg, &Shared::in_memory(cfg))
+}
+
+pub fn scaling_table(results: &[ScalingResult]) -> Table {
+    let mut table = Table::new();
+    table.add_row(row![
+        br->"threads",
+        br->"nodes",
+        br->"sec",
+        br->"Mn/s",
+        br->"speedup",
+        br->"efficiency",
+    ]);
+    for result in results {
+        table.add_row(row![
+            r->result.threads,
+            r->result.nodes,
+            r->format!("{:.3}", result.duration_sec),
+            r->format!("{:.3}", result.m_nodes_per_sec),
+            r->format!("{:.2}", result.speedup),
+            r->format!("{:.2}", result.efficiency),
+        ]);
+    }
+    table
+}
+
+/// Write the results as a JSON list, or as CSV with one row per thread count
+pub fn write_scaling<W: Write>(
+    mut writer: W,
+    results: &[ScalingResult],
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Table => unreachable!("tables are printed by the caller"),
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, results)?;
+            writeln!(writer)?;
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for result in results {
+                writer.serialize(result)?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
@@ -3,17 +3,24 @@ use super::*;
 use std::ops::{Add, AddAssign};
 use std::time::Instant;
 
-use prettytable::Row;
+use prettytable::{Row, Table};
+use serde::Serialize;
 
 use movelist::MoveCounter;
 
+#[derive(Serialize)]
 pub struct Stats {
+    #[serde(skip)]
     start: Instant,
     pub depth: u8,
     pub count: MoveCounter,
     pub cache_stats: CacheStats,
+    /// Percentage of cache entries in use after the run
+    pub cache_fill: f64,
     pub duration_sec: f64,
     pub m_nodes_per_sec: f64,
+    /// Work done by each thread of the pool, empty for single threaded runs
+    pub thread_loads: Vec<ThreadLoad>,
 }
 
 impl Stats {
@@ -23,8 +30,10 @@ impl Stats {
             start: Instant::now(),
             count: MoveCounter::default(),
             cache_stats: CacheStats::default(),
+            cache_fill: 0f64,
             duration_sec: 0f64,
             m_nodes_per_sec: 0f64,
+            thread_loads: Vec::new(),
         }
     }
 
@@ -41,11 +50,12 @@ impl Stats {
             br->"Mn/s",
         ];
 
+        if cfg.multithreading {
+            row.add_cell(cell!(br->"% util"));
+        }
+
         if cfg.detailed {
-            let detailed_headers = ["capt.", "ep", "castles", "promo."];
-            for c in detailed_headers {
-                row.add_cell(cell!(br->c))
-            }
+            add_detailed_headers(&mut row);
         }
 
         if cfg.caching {
@@ -54,11 +64,18 @@ impl Stats {
                 "hits",
                 "misses",
                 "collisions",
+                "evictions",
                 "hit nodes",
                 "% cached nodes",
+                "% fill",
             ] {
                 row.add_cell(cell!(br->c))
             }
+            if cfg.verify {
+                for c in ["torn", "verified", "mismatches"] {
+                    row.add_cell(cell!(br->c))
+                }
+            }
         }
         row
     }
@@ -71,25 +88,24 @@ impl Stats {
             r->format!("{:.3}", self.m_nodes_per_sec),
         ];
 
-        if cfg.detailed {
-            let detailed_info = [
-                self.count.captures,
-                self.count.ep,
-                self.count.castles,
-                self.count.promotions,
-            ];
-
-            for info in detailed_info {
-                row.add_cell(cell!(r->info))
+        if cfg.multithreading {
+            match self.utilisation(cfg.num_threads) {
+                Some(utilisation) => row.add_cell(cell!(r->format!("{:.1}", utilisation))),
+                None => row.add_cell(cell!(r->"-")),
             }
         }
 
+        if cfg.detailed {
+            add_detailed_info(&mut row, &self.count);
+        }
+
         if cfg.caching {
             let cache_info = [
                 self.cache_stats.accesses(),
                 self.cache_stats.hits,
                 self.cache_stats.misses,
                 self.cache_stats.collisions,
+                self.cache_stats.evictions,
                 self.cache_stats.hit_nodes,
             ];
             for info in cache_info {
@@ -97,18 +113,112 @@ impl Stats {
             }
             let cache_contribution =
                 self.cache_stats.hit_nodes as f64 / self.count.nodes as f64 * 100f64;
-            row.add_cell(cell!(r->format!("{:.3}", cache_contribution)))
+            row.add_cell(cell!(r->format!("{:.3}", cache_contribution)));
+            row.add_cell(cell!(r->format!("{:.1}", self.cache_fill)));
+            if cfg.verify {
+                let verify_info = [
+                    self.cache_stats.torn,
+                    self.cache_stats.verified,
+                    self.cache_stats.mismatches,
+                ];
+                for info in verify_info {
+                    row.add_cell(cell!(r->info))
+                }
+            }
         }
         row
     }
+
+    /// Percentage of the run the pool threads spent on jobs, if the run was
+    /// split into jobs
+    pub fn utilisation(&self, num_threads: usize) -> Option<f64> {
+        if self.thread_loads.is_empty() {
+            return None;
+        }
+        let busy_sec: f64 = self.thread_loads.iter().map(|load| load.busy_sec).sum();
+        Some(busy_sec / (num_threads as f64 * self.duration_sec) * 100.0)
+    }
+
+    /// A table of the work done by each thread
+    pub fn load_table(&self) -> Table {
+        let mut table = Table::new();
+        table.add_row(row![br->"thread", br->"jobs", br->"nodes", br->"busy sec", br->"% busy"]);
+        for (i, load) in self.thread_loads.iter().enumerate() {
+            table.add_row(row![
+                r->i,
+                r->load.jobs,
+                r->load.nodes,
+                r->format!("{:.3}", load.busy_sec),
+                r->format!("{:.1}", load.busy_sec / self.duration_sec * 100.0),
+            ]);
+        }
+        table
+    }
+
+    pub fn divide_start_row(cfg: &Config) -> Row {
+        let mut row = row![br->"move", br->"nodes"];
+        if cfg.detailed {
+            add_detailed_headers(&mut row);
+        }
+        row
+    }
+
+    pub fn divide_row(mv: &str, count: &MoveCounter, cfg: &Config) -> Row {
+        let mut row = row![r->mv, r->count.nodes];
+        if cfg.detailed {
+            add_detailed_info(&mut row, count);
+        }
+        row
+    }
+}
+
+fn add_detailed_headers(row: &mut Row) {
+    let detailed_headers = [
+        "capt.", "ep", "castles", "promo.", "checks", "disc.", "double", "mates",
+    ];
+    for c in detailed_headers {
+        row.add_cell(cell!(br->c))
+    }
+}
+
+fn add_detailed_info(row: &mut Row, count: &MoveCounter) {
+    let detailed_info = [
+        count.captures,
+        count.ep,
+        count.castles,
+        count.promotions,
+        count.checks,
+        count.discovered_checks,
+        count.double_checks,
+        count.checkmates,
+    ];
+    for info in detailed_info {
+        row.add_cell(cell!(r->info))
+    }
+}
+
+/// Work done by one thread of the pool during a run
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ThreadLoad {
+    pub jobs: u64,
+    pub nodes: u64,
+    pub busy_sec: f64,
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize)]
 pub struct CacheStats {
     pub hits: u64,
     pub misses: u64,
     pub collisions: u64,
     pub hit_nodes: u64,
+    /// Entries replaced by a write to a full bucket
+    pub evictions: u64,
+    /// Entries matching the key which failed their checksum
+    pub torn: u64,
+    /// Hits recomputed from scratch in verification mode
+    pub verified: u64,
+    /// Verified hits whose cached counts were wrong
+    pub mismatches: u64,
 }
 
 impl CacheStats {
@@ -126,6 +236,10 @@ impl Add for CacheStats {
             misses: self.misses + rhs.misses,
             collisions: self.collisions + rhs.collisions,
             hit_nodes: self.hit_nodes + rhs.hit_nodes,
+            evictions: self.evictions + rhs.evictions,
+            torn: self.torn + rhs.torn,
+            verified: self.verified + rhs.verified,
+            mismatches: self.mismatches + rhs.mismatches,
         }
     }
 }
@@ -136,5 +250,9 @@ impl AddAssign for CacheStats {
         self.misses += rhs.misses;
         self.collisions += rhs.collisions;
         self.hit_nodes += rhs.hit_nodes;
+        self.evictions += rhs.evictions;
+        self.torn += rhs.torn;
+        self.verified += rhs.verified;
+        self.mismatches += rhs.mismatches;
     }
 }
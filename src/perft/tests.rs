@@ -3,6 +3,14 @@ use super::*;
 use test_case::test_case;
 
 use constants::fen::*;
+use constants::DEFAULT_CACHE_SIZE;
+use position::Violation;
+use types::ColorT;
+
+/// Run perft with a new cache and thread pool
+fn run_perft<T: SizedEntry + 'static>(pos: &Position, depth: u8, cfg: &Config) -> Stats {
+    perft::<T>(pos, depth, cfg, &Shared::in_memory(cfg))
+}
 
 /// Standard test suite
 #[test_case(STARTING_FEN, vec![20, 400, 8902, 197281, 4865609, 119060324], 6; "startpos")]
@@ -14,11 +22,50 @@ use constants::fen::*;
 fn perft_suite(fen: &str, expected_nodes: Vec<u64>, depth: u8) {
     for (exp_node_count, depth) in zip(expected_nodes, 1..=depth) {
         let pos = Position::from_fen(fen).unwrap();
-        let result = perft::<Entry2xU64>(&pos, depth, &cfg::Config::test_cfg());
+        let result = run_perft::<Entry2xU64>(&pos, depth, &cfg::Config::test_cfg());
         assert_eq!(exp_node_count, result.count.nodes, "depth {}", depth)
     }
 }
 
+/// Detailed counts from the reference perft tables: nodes, captures, ep,
+/// castles, promotions, checks, discovered checks, double checks, checkmates
+#[test_case(STARTING_FEN, 5, [4865609, 82719, 258, 0, 0, 27351, 6, 0, 347]; "startpos")]
+#[test_case(TEST_2, 3, [97862, 17102, 45, 3162, 0, 993, 0, 0, 1]; "testpos2 depth 3")]
+#[test_case(TEST_2, 4, [4085603, 757163, 1929, 128013, 15172, 25523, 42, 6, 43]; "testpos2 depth 4")]
+#[test_case(TEST_3, 5, [674624, 52051, 1165, 0, 0, 52950, 1292, 3, 0]; "testpos3 depth 5")]
+#[test_case(TEST_3, 6, [11030083, 940350, 33325, 0, 7552, 452473, 26067, 0, 2733]; "testpos3 depth 6")]
+#[test_case(TEST_4, 4, [422333, 131393, 0, 7795, 60032, 15492, 19, 0, 5]; "testpos4")]
+#[test_case("5k2/8/8/8/8/8/8/5KR1 w G - 0 1", 1, [13, 0, 0, 1, 0, 2, 0, 0, 0]; "chess960 castle checks")]
+fn detailed_perft_suite(fen: &str, depth: u8, expected: [u64; 9]) {
+    let pos = Position::from_fen(fen).unwrap();
+    let cfg = cfg::Config::new(true, DEFAULT_CACHE_SIZE, true);
+    let count = run_perft::<Entry6xU64>(&pos, depth, &cfg).count;
+    let found = [
+        count.nodes,
+        count.captures as u64,
+        count.ep as u64,
+        count.castles as u64,
+        count.promotions as u64,
+        count.checks as u64,
+        count.discovered_checks as u64,
+        count.double_checks as u64,
+        count.checkmates as u64,
+    ];
+    assert_eq!(found, expected)
+}
+
+/// Detailed counts should not depend on the cache
+#[test]
+fn detailed_perft_without_cache() {
+    let pos = Position::from_fen(TEST_2).unwrap();
+    let cached =
+        run_perft::<Entry6xU64>(&pos, 3, &cfg::Config::new(true, DEFAULT_CACHE_SIZE, true));
+    let uncached = run_perft::<Entry6xU64>(&pos, 3, &cfg::Config::new(true, 0, true));
+    assert_eq!(cached.count.checks, uncached.count.checks);
+    assert_eq!(cached.count.checkmates, uncached.count.checkmates);
+    assert_eq!(cached.count.captures, uncached.count.captures);
+}
+
 /// Test suite for testing a variety of niche rules and mechanics.
 #[test_case("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 6, 1134888; "illegal ep move #1")]
 #[test_case("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1", 6, 1015133; "illegal ep move #2")]
@@ -37,13 +84,99 @@ fn perft_suite(fen: &str, expected_nodes: Vec<u64>, depth: u8) {
 fn talk_chess_perft_tests(fen: &str, depth: u8, expected_nodes: u64) {
     let pos = Position::from_fen(fen).unwrap();
     assert_eq!(
-        perft::<Entry2xU64>(&pos, depth, &cfg::Config::test_cfg())
+        run_perft::<Entry2xU64>(&pos, depth, &cfg::Config::test_cfg())
             .count
             .nodes,
         expected_nodes
     );
 }
 
+/// The search must count the same with either move generator
+#[test_case(TEST_2, 4; "testpos2")]
+#[test_case(TEST_3, 5; "testpos3")]
+#[test_case("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 5; "illegal ep move")]
+#[test_case("r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1", 3; "castling prevented")]
+fn perft_pseudo_legal_backend(fen: &str, depth: u8) {
+    let pos = Position::from_fen(fen).unwrap();
+    let cfg = cfg::Config::new(true, DEFAULT_CACHE_SIZE, true);
+    let legal = run_perft::<Entry6xU64>(&pos, depth, &cfg).count;
+    let cfg = cfg::Config {
+        backend: Backend::PseudoLegal,
+        ..cfg
+    };
+    assert_eq!(run_perft::<Entry6xU64>(&pos, depth, &cfg).count, legal);
+}
+
+/// Chess960 positions, with castling rights in Shredder-FEN and X-FEN
+#[test_case("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", vec![21, 528, 12189, 326672]; "960 #1")]
+#[test_case("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", vec![21, 807, 18002, 667366]; "960 #2")]
+#[test_case("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", vec![20, 479, 10471, 273318]; "960 #3")]
+#[test_case("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", vec![22, 593, 13440, 382958]; "960 #4")]
+#[test_case("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", vec![28, 1120, 31058, 1171749]; "960 #5")]
+#[test_case("qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9", vec![29, 899, 26578, 824055]; "960 #6")]
+#[test_case("q1bnrkr1/ppppp2p/2n2p2/4b1p1/2NP4/8/PPP1PPPP/QNB1RRKB w ge - 1 9", vec![30, 860, 24566, 732757]; "960 #7")]
+#[test_case("qbn1brkr/ppp1p1p1/2n4p/3p1p2/P7/6PP/QPPPPP2/1BNNBRKR w HFhf - 0 9", vec![25, 635, 17054, 465806]; "960 #8")]
+#[test_case("qnnbbrkr/1p2ppp1/2pp3p/p7/1P5P/2NP4/P1P1PPP1/Q1NBBRKR w HFhf - 0 9", vec![24, 572, 15243, 384260]; "960 #9")]
+#[test_case("qn1rbbkr/ppp2p1p/1n1pp1p1/8/3P4/P6P/1PP1PPPK/QNNRBB1R w hd - 2 9", vec![28, 811, 23175, 679699]; "960 #10")]
+fn chess960_perft_suite(fen: &str, expected_nodes: Vec<u64>) {
+    let pos = Position::from_fen(fen).unwrap();
+    for (exp_node_count, depth) in zip(expected_nodes, 1..) {
+        let result = run_perft::<Entry2xU64>(&pos, depth, &cfg::Config::test_cfg());
+        assert_eq!(exp_node_count, result.count.nodes, "depth {}", depth)
+    }
+}
+
+/// Divide counts should sum to the perft count of the position
+#[test_case(STARTING_FEN, 3; "startpos")]
+#[test_case(TEST_2, 3; "testpos2")]
+#[test_case(TEST_3, 5; "testpos3")]
+#[test_case(TEST_4, 1; "testpos4 depth 1")]
+fn divide_sums_to_perft(fen: &str, depth: u8) {
+    let pos = Position::from_fen(fen).unwrap();
+    let cfg = cfg::Config::test_cfg();
+    let counts = divide::<Entry2xU64>(&pos, depth, &cfg);
+    let total = counts
+        .iter()
+        .fold(MoveCounter::default(), |a, (_, b)| a + *b);
+    let expected = run_perft::<Entry2xU64>(&pos, depth, &cfg).count;
+    assert_eq!(total.nodes, expected.nodes);
+    assert_eq!(total.captures, expected.captures);
+    assert_eq!(total.castles, expected.castles);
+}
+
+#[test]
+fn divide_startpos() {
+    let counts = perft_divide(
+        STARTING_FEN,
+        3,
+        &CacheOptions::default(),
+        &ThreadOptions::default(),
+        false,
+        Backend::default(),
+    )
+    .unwrap();
+    assert_eq!(counts.len(), 20);
+    assert_eq!(counts[0].0, "a2a3");
+    assert_eq!(counts[0].1.nodes, 380);
+    let e2e4 = counts.iter().find(|(mv, _)| mv == "e2e4").unwrap();
+    assert_eq!(e2e4.1.nodes, 600);
+}
+
+#[test_case(STARTING_FEN, "", STARTING_FEN; "no moves")]
+#[test_case(STARTING_FEN, "e2e4 e7e5 g1f3", "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"; "opening")]
+#[test_case(TEST_2, "e1g1 a6e2", "r3k2r/p1ppqpb1/1n2pnp1/3PN3/1p2P3/2N2Q1p/PPPBbPPP/R4RK1 w kq - 0 2"; "castle and capture")]
+fn apply_uci_moves_to_position(fen: &str, moves: &str, expected_fen: &str) {
+    let pos = Position::from_fen(fen).unwrap();
+    let result = apply_uci_moves(&pos, moves).unwrap();
+    assert_eq!(result.to_fen(), expected_fen)
+}
+
+#[test]
+fn apply_illegal_uci_move() {
+    let pos = Position::new_start_pos();
+    assert_eq!(apply_uci_moves(&pos, "e2e4 e2e4").err(), Some("e2e4"))
+}
+
 /// Intensive perft tests. Keep ignore flag to prevent from being
 /// run in a normal test suite.
 #[ignore]
@@ -54,6 +187,338 @@ fn talk_chess_perft_tests(fen: &str, depth: u8, expected_nodes: u64) {
 #[test_case(TEST_6, 6923051137, 6; "testpos5")]
 fn deep_perft_suite(fen: &str, expected_nodes: u64, depth: u8) {
     let pos = Position::from_fen(fen).unwrap();
-    let result = perft::<Entry2xU64>(&pos, depth, &cfg::Config::test_cfg());
+    let result = run_perft::<Entry2xU64>(&pos, depth, &cfg::Config::test_cfg());
     assert_eq!(result.count.nodes, expected_nodes)
 }
+
+#[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400",
+    STARTING_FEN, vec![(1, 20), (2, 400)]; "epd position")]
+#[test_case("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1 ;D3 2812;D1 14 ;",
+    TEST_3, vec![(1, 14), (3, 2812)]; "fen position")]
+fn parse_epd_line(line: &str, expected_fen: &str, expected_counts: Vec<(u8, u64)>) {
+    let entry = EpdEntry::parse(line).unwrap();
+    assert_eq!(entry.fen, expected_fen);
+    assert_eq!(entry.expected, expected_counts);
+}
+
+#[test_case("8/8/8/8/8/8/8/8 w - - ;D1 0",
+    epd::EpdError::InvalidFen(FenError::IllegalPosition(Violation::KingCount { color: ColorT::White, count: 0 })); "no kings")]
+#[test_case("4k3/8/8/8/8/8/8/4K3 w - - ;D1",
+    epd::EpdError::InvalidOperation("D1".to_string()); "missing count")]
+#[test_case("4k3/8/8/8/8/8/8/4K3 w - - ;X1 5",
+    epd::EpdError::InvalidOperation("X1 5".to_string()); "unknown operation")]
+#[test_case("4k3/8/8/8/8/8/8/4K3 w - - ;D1 five",
+    epd::EpdError::InvalidOperation("D1 five".to_string()); "invalid count")]
+fn parse_invalid_epd_line(line: &str, expected: epd::EpdError) {
+    assert_eq!(EpdEntry::parse(line).unwrap_err(), expected);
+}
+
+#[test]
+fn write_json_records() {
+    let cfg = cfg::Config::test_cfg();
+    let pos = Position::from_fen(TEST_2).unwrap();
+    let stats = run_perft::<Entry2xU64>(&pos, 2, &cfg);
+    let records = [Record {
+        fen: TEST_2,
+        stats: &stats,
+    }];
+
+    let mut buf = Vec::new();
+    output::write_records(&mut buf, &cfg, &records, OutputFormat::Json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    assert_eq!(json["config"]["cache_size"], DEFAULT_CACHE_SIZE);
+    assert_eq!(json["results"][0]["fen"], TEST_2);
+    assert_eq!(json["results"][0]["depth"], 2);
+    assert_eq!(json["results"][0]["count"]["nodes"], 2039);
+}
+
+#[test]
+fn write_csv_records() {
+    let cfg = cfg::Config::test_cfg();
+    let pos = Position::from_fen(TEST_3).unwrap();
+    let stats: Vec<Stats> = (1..=3)
+        .map(|d| run_perft::<Entry2xU64>(&pos, d, &cfg))
+        .collect();
+    let records: Vec<Record> = stats
+        .iter()
+        .map(|stats| Record { fen: TEST_3, stats })
+        .collect();
+
+    let mut buf = Vec::new();
+    output::write_records(&mut buf, &cfg, &records, OutputFormat::Csv).unwrap();
+    let csv = String::from_utf8(buf).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("fen,depth,nodes,"));
+    assert!(lines[3].starts_with(&format!("{TEST_3},3,2812,")));
+}
+
+#[test]
+fn write_divide_output() {
+    let cfg = cfg::Config::test_cfg();
+    let counts = perft_divide(
+        STARTING_FEN,
+        2,
+        &CacheOptions::default(),
+        &ThreadOptions::default(),
+        false,
+        Backend::default(),
+    )
+    .unwrap();
+
+    let mut buf = Vec::new();
+    output::write_divide(&mut buf, &cfg, STARTING_FEN, 2, &counts, OutputFormat::Json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+    assert_eq!(json["depth"], 2);
+    assert_eq!(json["moves"].as_array().unwrap().len(), 20);
+    assert_eq!(json["moves"][0]["move"], "a2a3");
+    assert_eq!(json["moves"][0]["nodes"], 20);
+    assert_eq!(json["total"]["nodes"], 400);
+
+    let mut buf = Vec::new();
+    output::write_divide(&mut buf, &cfg, STARTING_FEN, 2, &counts, OutputFormat::Csv).unwrap();
+    let csv = String::from_utf8(buf).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 21);
+    assert!(lines[0].starts_with("move,nodes,"));
+    assert!(lines[1].starts_with("a2a3,20,"));
+}
+
+#[test]
+fn perft_cache_file() {
+    let path = std::env::temp_dir().join(format!("rperft-{}-perft.cache", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let cfg = cfg::Config {
+        cache_file: Some(path.clone()),
+        ..cfg::Config::test_cfg()
+    };
+    let pos = Position::from_fen(TEST_2).unwrap();
+
+    let first = perft_runs::<Entry2xU64>(&[(pos, 4)], &cfg).unwrap();
+    assert_eq!(first[0].count.nodes, 4085603);
+
+    // Every root move is found in the cache written by the first run
+    let second = perft_runs::<Entry2xU64>(&[(pos, 4)], &cfg).unwrap();
+    assert_eq!(second[0].count.nodes, 4085603);
+    assert_eq!(second[0].cache_stats.hit_nodes, 4085603);
+
+    assert!(perft_runs::<Entry6xU64>(&[(pos, 4)], &cfg).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn perft_runs_share_cache() {
+    // Small enough for the fill to be measured over every entry
+    let cfg = cfg::Config {
+        cache_size: 1 << 20,
+        ..cfg::Config::test_cfg()
+    };
+    let pos = Position::from_fen(TEST_2).unwrap();
+    let stats = perft_runs::<Entry2xU64>(&[(pos, 3), (pos, 4), (pos, 4)], &cfg).unwrap();
+
+    assert!(stats[0].cache_fill > 0.0);
+    assert!(stats[1].cache_fill >= stats[0].cache_fill);
+    // The root moves of the repeated run were all cached by the previous one
+    assert_eq!(stats[2].count.nodes, 4085603);
+    assert_eq!(stats[2].cache_stats.hit_nodes, 4085603);
+}
+
+#[test_case(Replacement::Always; "always")]
+#[test_case(Replacement::Depth; "depth")]
+#[test_case(Replacement::Nodes; "nodes")]
+#[test_case(Replacement::TwoTier; "two tier")]
+fn perft_replacement(replacement: Replacement) {
+    // A small cache, so that full buckets are replaced
+    let cfg = cfg::Config {
+        cache_size: 1 << 14,
+        replacement,
+        ..cfg::Config::test_cfg()
+    };
+    let pos = Position::from_fen(TEST_2).unwrap();
+    let stats = run_perft::<Entry6xU64>(&pos, 4, &cfg);
+    assert_eq!(stats.count.nodes, 4085603);
+    assert!(stats.cache_stats.evictions > 0);
+}
+
+#[test_case("32000000", Ok(32_000_000); "bytes")]
+#[test_case("0", Ok(0); "disabled")]
+#[test_case("64K", Ok(64 << 10); "kilobytes")]
+#[test_case("512M", Ok(512 << 20); "megabytes")]
+#[test_case("4g", Ok(4 << 30); "lowercase gigabytes")]
+#[test_case("2GiB", Ok(2 << 30); "binary unit")]
+fn parse_cache_size(s: &str, expected: Result<usize, ()>) {
+    assert_eq!(cfg::parse_cache_size(s).map_err(|_| ()), expected);
+}
+
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn parse_terabyte_cache_size() {
+    assert_eq!(cfg::parse_cache_size("1T"), Ok(1 << 40));
+}
+
+// A terabyte is too large for a 32 bit usize
+#[cfg(target_pointer_width = "32")]
+#[test]
+fn parse_terabyte_cache_size() {
+    assert!(cfg::parse_cache_size("1T").is_err());
+}
+
+#[test_case("M"; "no number")]
+#[test_case("12X"; "unknown unit")]
+#[test_case("-1M"; "negative")]
+#[test_case("99999999999T"; "overflow")]
+fn parse_invalid_cache_size(s: &str) {
+    assert!(cfg::parse_cache_size(s).is_err());
+}
+
+#[test]
+fn perft_verify_cache() {
+    let cfg = cfg::Config {
+        verify: true,
+        ..cfg::Config::test_cfg()
+    };
+    let pos = Position::from_fen(TEST_2).unwrap();
+    let stats = run_perft::<Checked<Entry2xU64>>(&pos, 5, &cfg);
+    assert_eq!(stats.count.nodes, 193690690);
+    assert!(stats.cache_stats.hits + stats.cache_stats.misses > 0);
+    assert!(stats.cache_stats.verified <= stats.cache_stats.hits);
+    assert!(stats.cache_stats.verified > 0);
+    assert_eq!(stats.cache_stats.torn, 0);
+    assert_eq!(stats.cache_stats.mismatches, 0);
+}
+
+#[test]
+fn perft_verify_detailed_cache() {
+    let cfg = cfg::Config {
+        verify: true,
+        ..cfg::Config::new(true, DEFAULT_CACHE_SIZE, true)
+    };
+    let pos = Position::new_start_pos();
+    let stats = run_perft::<Checked<Entry6xU64>>(&pos, 5, &cfg);
+    assert_eq!(stats.count.checkmates, 347);
+    assert!(stats.cache_stats.verified > 0);
+    assert_eq!(stats.cache_stats.mismatches, 0);
+}
+
+#[test_case(1; "root moves")]
+#[test_case(2; "default")]
+#[test_case(3; "deep split")]
+fn perft_split_depth(split_depth: u8) {
+    let cfg = cfg::Config {
+        split_depth,
+        ..cfg::Config::test_cfg()
+    };
+    let pos = Position::from_fen(TEST_3).unwrap();
+    let stats = run_perft::<Entry2xU64>(&pos, 5, &cfg);
+    assert_eq!(stats.count.nodes, 674624);
+
+    // Every job is accounted to a thread of the pool
+    let loads = &stats.thread_loads;
+    assert!(!loads.is_empty() && loads.len() <= cfg.num_threads);
+    assert_eq!(loads.iter().map(|l| l.nodes).sum::<u64>(), 674624);
+    let n_jobs: u64 = loads.iter().map(|l| l.jobs).sum();
+    let expected_jobs = [14, 191, 2812][split_depth as usize - 1];
+    assert_eq!(n_jobs, expected_jobs);
+}
+
+// Jobs must search at least two plies, so shallower searches are not split
+#[test_case(4; "one ply jobs")]
+#[test_case(9; "deeper than the search")]
+fn perft_split_too_deep_runs_on_one_thread(split_depth: u8) {
+    let cfg = cfg::Config {
+        split_depth,
+        ..cfg::Config::test_cfg()
+    };
+    let pos = Position::from_fen(TEST_3).unwrap();
+    let stats = run_perft::<Entry2xU64>(&pos, 5, &cfg);
+    assert_eq!(stats.count.nodes, 674624);
+    assert!(stats.thread_loads.is_empty());
+}
+
+#[test]
+fn perft_single_thread_has_no_loads() {
+    let cfg = cfg::Config::new(false, DEFAULT_CACHE_SIZE, false);
+    let pos = Position::from_fen(TEST_3).unwrap();
+    let stats = run_perft::<Entry2xU64>(&pos, 5, &cfg);
+    assert_eq!(stats.count.nodes, 674624);
+    assert!(stats.thread_loads.is_empty());
+    assert_eq!(stats.utilisation(cfg.num_threads), None);
+}
+
+#[test_case(1, vec![1]; "one")]
+#[test_case(4, vec![1, 2, 4]; "power of two")]
+#[test_case(6, vec![1, 2, 4, 6]; "six")]
+fn scaling_thread_counts(max: usize, expected: Vec<usize>) {
+    assert_eq!(scaling::thread_counts(max), expected);
+}
+
+#[test]
+fn scaling_runs() {
+    let pos = Position::from_fen(TEST_3).unwrap();
+    let threads = ThreadOptions {
+        pin: true,
+        ..ThreadOptions::default()
+    };
+    let results = scaling::run_scaling(
+        &pos,
+        5,
+        &CacheOptions::default(),
+        &threads,
+        false,
+        Backend::default(),
+        &[1, 2],
+    );
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|r| r.nodes == 674624));
+    assert_eq!(results[0].speedup, 1.0);
+    assert_eq!(results[1].efficiency, results[1].speedup / 2.0);
+}
+
+#[test]
+fn config_thread_count() {
+    let threads = ThreadOptions {
+        threads: Some(3),
+        ..ThreadOptions::default()
+    };
+    let cfg = cfg::Config::with_options(
+        &threads,
+        &CacheOptions::default(),
+        false,
+        Backend::default(),
+    );
+    assert_eq!(cfg.num_threads, 3);
+
+    let single = ThreadOptions {
+        multithreading: false,
+        ..threads
+    };
+    let cfg =
+        cfg::Config::with_options(&single, &CacheOptions::default(), false, Backend::default());
+    assert_eq!(cfg.num_threads, 1);
+}
+
+#[test_case(STARTING_FEN; "startpos")]
+#[test_case(TEST_2; "testpos2")]
+#[test_case(TEST_3; "testpos3")]
+#[test_case(TEST_4; "testpos4")]
+#[test_case(TEST_5; "testpos5")]
+#[test_case(TEST_6; "testpos6")]
+fn count_depth_2_matches_make_move(fen: &str) {
+    // Compare at every position one ply down, to cover more move types
+    let pos = Position::from_fen(fen).unwrap();
+    for mv in pos.legal_moves() {
+        let child = pos.make_move(&mv);
+        let mut moves = MoveVec::new();
+        generate_all(&child, &mut moves);
+        let expected: u64 = moves
+            .iter()
+            .map(|mv| count_moves(&child.make_move(mv), false, Backend::Legal).nodes)
+            .sum();
+        assert_eq!(
+            count_depth_2(&child, Backend::Legal).nodes,
+            expected,
+            "{}",
+            child.to_fen()
+        );
+    }
+}
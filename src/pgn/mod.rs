@@ -0,0 +1,155 @@
+/// Reading and writing of games in Portable Game Notation (PGN)
+use super::*;
+
+use std::time::Instant;
+
+use mv::Move;
+use position::{FenError, Position};
+use san::MoveParseError;
+
+mod reader;
+mod writer;
+
+pub use reader::{PgnError, PgnReader};
+
+/// A game read from a PGN file. Only the mainline moves are kept, in the
+/// notation they were written in
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    pub result: Option<String>,
+}
+
+/// Error returned when the moves of a game cannot be replayed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The FEN tag is not a valid position
+    InvalidFen(FenError),
+    /// The first move which could not be resolved to a legal move, with the
+    /// 1-based ply it was played on and the position it was played in
+    IllegalMove {
+        ply: usize,
+        san: String,
+        fen: String,
+        error: MoveParseError,
+    },
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::InvalidFen(e) => write!(f, "invalid FEN tag: {e}"),
+            ReplayError::IllegalMove {
+                ply, error, fen, ..
+            } => {
+                write!(f, "ply {ply}: {error} ({fen})")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl PgnGame {
+    /// The value of a tag, if present
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The position the game starts from, given by the FEN tag or otherwise
+    /// the standard starting position
+    pub fn start_pos(&self) -> Result<Position, FenError> {
+        match self.tag("FEN") {
+            Some(fen) => Position::from_fen(fen),
+            None => Ok(Position::new_start_pos()),
+        }
+    }
+
+    /// Resolve and play every mainline move from the starting position,
+    /// returning the moves played and the final position
+    ///
+    /// ```
+    /// use rperft::pgn::PgnReader;
+    ///
+    /// let pgn = "[Event \"?\"]\n\n1. e4 e5 2. Nf3 {best by test} Nc6 (2... d6) 3. Bb5 1-0\n";
+    /// let game = PgnReader::new(pgn.as_bytes()).next().unwrap().unwrap();
+    /// let (moves, pos) = game.replay().unwrap();
+    /// assert_eq!(moves.len(), 5);
+    /// assert_eq!(pos.to_fen(), "r1bqkbnr/pppp1ppp/2n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3");
+    /// ```
+    pub fn replay(&self) -> Result<(Vec<Move>, Position), ReplayError> {
+        let mut pos = self.start_pos().map_err(ReplayError::InvalidFen)?;
+        let mut moves = Vec::with_capacity(self.moves.len());
+        for (i, san) in self.moves.iter().enumerate() {
+            match pos.parse_move(san) {
+                Ok(mv) => {
+                    pos = pos.make_move(&mv);
+                    moves.push(mv);
+                }
+                Err(error) => {
+                    return Err(ReplayError::IllegalMove {
+                        ply: i + 1,
+                        san: san.clone(),
+                        fen: pos.to_fen(),
+                        error,
+                    })
+                }
+            }
+        }
+        Ok((moves, pos))
+    }
+}
+
+/// Stream the games of a PGN file, replaying each through the move generator
+/// and printing the first illegal move of each game which fails. Returns
+/// false if any game could not be read or replayed
+pub fn replay_pgn_file(path: &str) -> bool {
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            log::error!("Could not read {path}: {e}");
+            return false;
+        }
+    };
+
+    let start = Instant::now();
+    let (mut n_games, mut n_plies, mut n_failed) = (0u64, 0u64, 0u64);
+    for game in PgnReader::new(std::io::BufReader::new(file)) {
+        n_games += 1;
+        let game = match game {
+            Ok(game) => game,
+            Err(e) => {
+                println!("game {n_games}: {e}");
+                n_failed += 1;
+                // Io errors are not recoverable
+                if let PgnError::Io(_) = e {
+                    break;
+                }
+                continue;
+            }
+        };
+        match game.replay() {
+            Ok((moves, _)) => n_plies += moves.len() as u64,
+            Err(e) => {
+                let white = game.tag("White").unwrap_or("?");
+                let black = game.tag("Black").unwrap_or("?");
+                println!("game {n_games} ({white} - {black}): {e}");
+                n_failed += 1;
+            }
+        }
+    }
+
+    let duration = start.elapsed().as_secs_f64();
+    println!();
+    println!(
+        "{n_games} games; {n_plies} plies; {n_failed} failed; {duration:.3} sec; \
+         {:.0} games/s; {:.0} plies/s",
+        n_games as f64 / duration,
+        n_plies as f64 / duration
+    );
+    n_failed == 0
+}
@@ -0,0 +1,320 @@
+/// Streaming parser splitting a PGN file into games
+use super::*;
+
+use std::io::BufRead;
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// The tag pairs and the unparsed movetext of a game
+type RawGame = (Vec<(String, String)>, String);
+
+#[derive(Debug)]
+pub enum PgnError {
+    Io(std::io::Error),
+    /// A line in the tag section which is not a `[Name "value"]` pair
+    InvalidTag {
+        line: usize,
+        text: String,
+    },
+}
+
+impl std::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::Io(e) => write!(f, "{e}"),
+            PgnError::InvalidTag { line, text } => write!(f, "line {line}: invalid tag '{text}'"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+/// An iterator over the games of a PGN source, read one line at a time so
+/// that large files can be streamed
+///
+/// ```
+/// use rperft::pgn::PgnReader;
+///
+/// let pgn = "[White \"A\"]\n\n1. d4 d5 *\n\n[White \"B\"]\n\n1. c4 $1 e5 ; English\n1/2-1/2\n";
+/// let games: Vec<_> = PgnReader::new(pgn.as_bytes()).map(Result::unwrap).collect();
+/// assert_eq!(games.len(), 2);
+/// assert_eq!(games[1].tag("White"), Some("B"));
+/// assert_eq!(games[1].moves, vec!["c4", "e5"]);
+/// assert_eq!(games[1].result.as_deref(), Some("1/2-1/2"));
+/// ```
+pub struct PgnReader<R: BufRead> {
+    source: R,
+    line_no: usize,
+    // A tag line read while finishing the previous game
+    pending: Option<String>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(source: R) -> Self {
+        Self {
+            source,
+            line_no: 0,
+            pending: None,
+        }
+    }
+
+    fn read_line(&mut self) -> Option<Result<String, PgnError>> {
+        if let Some(line) = self.pending.take() {
+            self.line_no += 1;
+            return Some(Ok(line));
+        }
+        let mut line = String::new();
+        match self.source.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                self.line_no += 1;
+                Some(Ok(line))
+            }
+            Err(e) => Some(Err(PgnError::Io(e))),
+        }
+    }
+
+    /// Read the tag section and the movetext of the next game. A game ends
+    /// at the next tag line outside a comment, or at the end of the source
+    fn read_game(&mut self) -> Option<Result<RawGame, PgnError>> {
+        let mut tags = Vec::new();
+        let mut movetext = String::new();
+        let mut in_comment = false;
+        let mut error = None;
+
+        while let Some(line) = self.read_line() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            let trimmed = line.trim();
+
+            // Lines starting with % are escaped from parsing
+            if !in_comment && trimmed.starts_with('%') {
+                continue;
+            }
+            if !in_comment && trimmed.starts_with('[') {
+                if !movetext.trim().is_empty() {
+                    self.pending = Some(line);
+                    self.line_no -= 1;
+                    break;
+                }
+                match parse_tag(trimmed) {
+                    Some(tag) => tags.push(tag),
+                    None => {
+                        error.get_or_insert(PgnError::InvalidTag {
+                            line: self.line_no,
+                            text: trimmed.to_string(),
+                        });
+                    }
+                }
+                continue;
+            }
+
+            // Track multi line comments so that brackets inside are ignored
+            for c in line.chars() {
+                match c {
+                    '{' => in_comment = true,
+                    '}' => in_comment = false,
+                    _ => (),
+                }
+            }
+            movetext.push_str(&line);
+        }
+
+        if let Some(e) = error {
+            return Some(Err(e));
+        }
+        if tags.is_empty() && movetext.trim().is_empty() {
+            return None;
+        }
+        Some(Ok((tags, movetext)))
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (tags, movetext) = match self.read_game()? {
+            Ok(game) => game,
+            Err(e) => return Some(Err(e)),
+        };
+        let (moves, result) = parse_movetext(&movetext);
+        Some(Ok(PgnGame {
+            tags,
+            moves,
+            result,
+        }))
+    }
+}
+
+/// Parse a `[Name "value"]` tag pair, unescaping the value
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let (name, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            unescaped.push(chars.next()?);
+        } else {
+            unescaped.push(c);
+        }
+    }
+    Some((name.to_string(), unescaped))
+}
+
+/// Split movetext into its mainline moves and result, skipping move numbers,
+/// comments, variations and numeric annotation glyphs
+fn parse_movetext(movetext: &str) -> (Vec<String>, Option<String>) {
+    let mut moves = Vec::new();
+    let mut result = None;
+    let mut variation_depth = 0;
+    let mut chars = movetext.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '{' => {
+                chars.by_ref().find(|&c| c == '}');
+            }
+            ';' => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '(' => {
+                chars.next();
+                variation_depth += 1;
+            }
+            ')' => {
+                chars.next();
+                variation_depth -= 1;
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{};()".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                if variation_depth > 0 || token.starts_with('$') {
+                    continue;
+                }
+                if RESULTS.contains(&token.as_str()) {
+                    result = Some(token);
+                    continue;
+                }
+                // Strip move numbers, which may be joined to the move
+                let san = strip_move_number(&token);
+                if !san.is_empty() {
+                    moves.push(san.to_string());
+                }
+            }
+        }
+    }
+    (moves, result)
+}
+
+/// Strip a move number like `12.` or `12...` from the start of a token.
+/// Digits not followed by a dot are kept, as in castles written `0-0`
+fn strip_move_number(token: &str) -> &str {
+    let rest = token.trim_start_matches(|c: char| c.is_ascii_digit());
+    let san = rest.trim_start_matches('.');
+    if rest.len() < token.len() && san.len() < rest.len() {
+        san
+    } else {
+        token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    #[test_case("1. e4 e5 2. Nf3 *", vec!["e4", "e5", "Nf3"], Some("*"); "simple")]
+    #[test_case("1.e4 e5 2.Nf3 1-0", vec!["e4", "e5", "Nf3"], Some("1-0"); "joined move numbers")]
+    #[test_case("1. e4 {a {comment} e5 2... Nf6 0-1", vec!["e4", "e5", "Nf6"], Some("0-1");
+        "comment and black move number")]
+    #[test_case("1. e4 (1. d4 d5 (1... Nf6)) e5!? $2 ; rest of line\n2. Nf3",
+        vec!["e4", "e5!?", "Nf3"], None; "variations, nags and line comments")]
+    #[test_case("1. e4\n{multi\nline} e5 1/2-1/2", vec!["e4", "e5"], Some("1/2-1/2"); "multi line comment")]
+    #[test_case("1. e4 e5 2. 0-0", vec!["e4", "e5", "0-0"], None; "castle with zeros")]
+    #[test_case("1.0-0 0-0-0 2...O-O *", vec!["0-0", "0-0-0", "O-O"], Some("*");
+        "castles with joined move numbers")]
+    fn test_parse_movetext(movetext: &str, expected: Vec<&str>, result: Option<&str>) {
+        let (moves, found_result) = parse_movetext(movetext);
+        assert_eq!(moves, expected);
+        assert_eq!(found_result.as_deref(), result);
+    }
+
+    #[test_case(r#"[Event "F/S Return Match"]"#, "Event", "F/S Return Match"; "simple")]
+    #[test_case(r#"[Annotator "A \"quoted\" \\ name"]"#, "Annotator", r#"A "quoted" \ name"#; "escapes")]
+    fn test_parse_tag(line: &str, name: &str, value: &str) {
+        assert_eq!(parse_tag(line), Some((name.to_string(), value.to_string())));
+    }
+
+    #[test]
+    fn test_read_games() {
+        let pgn =
+            "[Event \"One\"]\n[Result \"1-0\"]\n\n1. e4 {[%clk 0:03:00]\n[not a tag]} e5 1-0\n\
+                   % escaped line\n\n[Event \"Two\"]\n[FEN \"4k3/8/8/8/8/8/8/4K2R w K - 0 1\"]\n\n\
+                   1. O-O Kd7 *\n";
+        let games: Vec<PgnGame> = PgnReader::new(pgn.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("One"));
+        assert_eq!(games[0].moves, vec!["e4", "e5"]);
+        assert_eq!(games[1].moves, vec!["O-O", "Kd7"]);
+        assert_eq!(
+            games[1].replay().unwrap().1.to_fen(),
+            "8/3k4/8/8/8/8/8/5RK1 w - - 2 2"
+        );
+    }
+
+    #[test]
+    fn test_invalid_tag() {
+        let pgn = "[Event \"One\"]\n[Site unquoted]\n\n1. e4 *\n\n[Event \"Two\"]\n\n1. d4 *\n";
+        let mut reader = PgnReader::new(pgn.as_bytes());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(PgnError::InvalidTag { line: 2, .. }))
+        ));
+        assert_eq!(reader.next().unwrap().unwrap().moves, vec!["d4"]);
+    }
+
+    #[test]
+    fn test_invalid_tag_in_later_games() {
+        let pgn = "[Event \"One\"]\n\n1. e4 *\n\n[Event \"Two\"]\n[Site unquoted]\n\n1. d4 *\n\n\
+                   [Event \"Three\"]\n[Date unquoted]\n\n1. c4 *\n";
+        let mut reader = PgnReader::new(pgn.as_bytes());
+        assert_eq!(reader.next().unwrap().unwrap().moves, vec!["e4"]);
+        assert!(matches!(
+            reader.next(),
+            Some(Err(PgnError::InvalidTag { line: 6, .. }))
+        ));
+        assert!(matches!(
+            reader.next(),
+            Some(Err(PgnError::InvalidTag { line: 11, .. }))
+        ));
+    }
+
+    #[test]
+    fn test_replay_illegal_move() {
+        let pgn = "1. e4 e5 2. Ke3 *";
+        let game = PgnReader::new(pgn.as_bytes()).next().unwrap().unwrap();
+        let expected = ReplayError::IllegalMove {
+            ply: 3,
+            san: "Ke3".to_string(),
+            fen: "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2".to_string(),
+            error: MoveParseError::Illegal("Ke3".to_string()),
+        };
+        assert_eq!(game.replay().unwrap_err(), expected);
+    }
+}
@@ -0,0 +1,212 @@
+/// Export of move sequences as PGN
+use super::*;
+
+use std::fmt;
+
+use game::Game;
+
+/// Movetext lines are wrapped so they never exceed this width
+const LINE_WIDTH: usize = 80;
+
+/// The Seven Tag Roster with the values used when a tag is unknown
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+impl PgnGame {
+    /// Build a game from a sequence of legal moves played from a starting
+    /// position. The moves are written in SAN and the result is set from the
+    /// final position. Non standard starting positions get SetUp and FEN tags
+    ///
+    /// ```
+    /// use rperft::pgn::PgnGame;
+    /// use rperft::Position;
+    ///
+    /// let mut pos = Position::new_start_pos();
+    /// let mut moves = Vec::new();
+    /// for uci in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+    ///     let mv = pos.parse_move(uci).unwrap();
+    ///     pos = pos.make_move(&mv);
+    ///     moves.push(mv);
+    /// }
+    /// let game = PgnGame::from_moves(&Position::new_start_pos(), &moves);
+    /// assert_eq!(game.result.as_deref(), Some("0-1"));
+    /// assert!(game.to_string().ends_with("\n1. f3 e5 2. g4 Qh4# 0-1\n"));
+    /// ```
+    pub fn from_moves(start: &Position, moves: &[Move]) -> Self {
+        let mut game = Game::new(*start);
+        let mut san = Vec::with_capacity(moves.len());
+        for mv in moves {
+            san.push(game.position().to_san(mv));
+            game.play(mv);
+        }
+        let result = game.result();
+
+        let mut tags: Vec<(String, String)> = SEVEN_TAG_ROSTER
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        tags[6].1 = result.to_string();
+        if *start != Position::new_start_pos() {
+            if start.is_chess960() {
+                tags.push(("Variant".to_string(), "Chess960".to_string()));
+            }
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), start.to_fen()));
+        }
+
+        Self {
+            tags,
+            moves: san,
+            result: Some(result.to_string()),
+        }
+    }
+}
+
+/// Escape a tag value, as quotes and backslashes must be preceded by a
+/// backslash
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl fmt::Display for PgnGame {
+    /// Write the game as PGN. The Seven Tag Roster is written first, in
+    /// order, with missing tags set to their unknown values
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = self.result.as_deref().unwrap_or("*");
+        for (name, default) in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => result,
+                _ => self.tag(name).unwrap_or(default),
+            };
+            writeln!(f, "[{name} \"{}\"]", escape(value))?;
+        }
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.iter().any(|(tag, _)| tag == name) {
+                writeln!(f, "[{name} \"{}\"]", escape(value))?;
+            }
+        }
+        writeln!(f)?;
+
+        // Number the moves from the starting position
+        let (mut number, mut wtm) = match self.start_pos() {
+            Ok(pos) => (pos.fullmove_clock as u32, pos.wtm),
+            Err(_) => (1, true),
+        };
+        let mut tokens = Vec::with_capacity(self.moves.len() * 3 / 2 + 1);
+        for (i, san) in self.moves.iter().enumerate() {
+            if wtm {
+                tokens.push(format!("{number}."));
+            } else if i == 0 {
+                tokens.push(format!("{number}..."));
+            }
+            tokens.push(san.clone());
+            number += !wtm as u32;
+            wtm = !wtm;
+        }
+        tokens.push(result.to_string());
+
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + 1 + token.len() > LINE_WIDTH {
+                writeln!(f)?;
+                line_len = 0;
+            }
+            if line_len > 0 {
+                write!(f, " ")?;
+                line_len += 1;
+            }
+            write!(f, "{token}")?;
+            line_len += token.len();
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    fn play(fen: &str, moves: &str) -> (Position, Vec<Move>) {
+        let start = Position::from_fen(fen).unwrap();
+        let mut pos = start;
+        let mut played = Vec::new();
+        for s in moves.split_whitespace() {
+            let mv = pos.parse_move(s).unwrap();
+            pos = pos.make_move(&mv);
+            played.push(mv);
+        }
+        (start, played)
+    }
+
+    #[test]
+    fn test_write_game() {
+        let (start, moves) = play(constants::fen::STARTING_FEN, "e4 e5 Qh5 Nc6 Bc4 Nf6 Qxf7");
+        let expected = "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n\
+                        [White \"?\"]\n[Black \"?\"]\n[Result \"1-0\"]\n\n\
+                        1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n";
+        assert_eq!(PgnGame::from_moves(&start, &moves).to_string(), expected);
+    }
+
+    #[test]
+    fn test_write_setup() {
+        let fen = "4k3/8/8/8/8/8/5PPP/4K2R b K - 3 41";
+        let (start, moves) = play(fen, "Kd7 O-O");
+        let pgn = PgnGame::from_moves(&start, &moves).to_string();
+        assert!(pgn.contains(
+            "[Result \"*\"]\n[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/5PPP/4K2R b K - 3 41\"]\n\n"
+        ));
+        assert!(pgn.ends_with("\n41... Kd7 42. O-O *\n"));
+    }
+
+    #[test_case("7k/5Q2/6K1/8/8/8/8/8 w - - 0 1", "Kh6", "1/2-1/2"; "stalemate")]
+    #[test_case("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1", "Qb8", "1-0"; "white mates")]
+    #[test_case("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1", "Qb7", "*"; "ongoing")]
+    #[test_case("7k/8/6K1/8/8/8/8/1N6 w - - 0 1", "Nc3", "1/2-1/2"; "insufficient material")]
+    fn test_result(fen: &str, moves: &str, result: &str) {
+        let (start, moves) = play(fen, moves);
+        let game = PgnGame::from_moves(&start, &moves);
+        assert_eq!(game.tag("Result"), Some(result));
+        assert_eq!(game.result.as_deref(), Some(result));
+    }
+
+    #[test]
+    fn test_escape_tags() {
+        let game = PgnGame {
+            tags: vec![
+                ("White".to_string(), "A \"B\" \\ C".to_string()),
+                ("Annotator".to_string(), "D".to_string()),
+            ],
+            ..Default::default()
+        };
+        let pgn = game.to_string();
+        assert!(pgn.contains("[White \"A \\\"B\\\" \\\\ C\"]\n[Black \"?\"]\n[Result \"*\"]\n[Annotator \"D\"]\n\n*\n"));
+        let read = PgnReader::new(pgn.as_bytes()).next().unwrap().unwrap();
+        assert_eq!(read.tag("White"), Some("A \"B\" \\ C"));
+    }
+
+    #[test]
+    fn test_round_trip_wrapped() {
+        let (start, moves) = play(
+            constants::fen::TEST_2,
+            "O-O-O O-O Kb1 Kh8 Rc1 Rg8 Rcd1 Rgf8 Ka1 Kg8 Kb1 Kh8 Ka1 Kg8 Kb1 Kh8 Ka1 Kg8 \
+             Kb1 Kh8 Ka1 Kg8 Kb1 Kh8 Ka1 Kg8 Kb1 Kh8 Ka1 Kg8 Kb1 Kh8",
+        );
+        let pgn = PgnGame::from_moves(&start, &moves).to_string();
+        let movetext = pgn.split("\n\n").nth(1).unwrap();
+        assert!(movetext.lines().count() > 1);
+        assert!(movetext.lines().all(|line| line.len() <= LINE_WIDTH));
+
+        let read = PgnReader::new(pgn.as_bytes()).next().unwrap().unwrap();
+        let (replayed, _) = read.replay().unwrap();
+        assert_eq!(replayed, moves);
+    }
+}
@@ -6,7 +6,7 @@ use types::{ColorT, PieceT};
 
 impl Position {
     /// Return a bitboard with all squares the opponent pieces are attacking
-    pub fn unsafe_sq<C: Color>(&self) -> BitBoard {
+    pub(crate) fn unsafe_sq<C: Color>(&self) -> BitBoard {
         // Remove our king from the occupancy bitboard to prevent the king from
         // blocking other squares attacked by sliding pieces
         let occ = self.occ ^ self.us.king;
@@ -21,7 +21,7 @@ impl Position {
     }
 
     /// Return a bitboard of opponent pieces giving check
-    pub fn checkers<C: Color>(&self) -> BitBoard {
+    pub(crate) fn checkers<C: Color>(&self) -> BitBoard {
         let mut checkers = constants::bb::EMPTY;
         checkers |= (C::l_cap(self.us.king) | C::r_cap(self.us.king)) & self.them.pawn;
         checkers |= self.us.king.rook_magic_lu(self.occ) & (self.them.rook | self.them.queen);
@@ -30,8 +30,17 @@ impl Position {
         checkers
     }
 
+    /// Return a bitboard of opponent pieces giving check, for either side to
+    /// move
+    pub(crate) fn stm_checkers(&self) -> BitBoard {
+        match self.stm {
+            ColorT::White => self.checkers::<White>(),
+            ColorT::Black => self.checkers::<Black>(),
+        }
+    }
+
     /// Return a bitboard of all pinned pieces
-    pub fn pinned(&self) -> BitBoard {
+    pub(crate) fn pinned(&self) -> BitBoard {
         let rooks = self.them.rook | self.them.queen;
         let bishops = self.them.bishop | self.them.queen;
         let occ = self.occ;
@@ -46,6 +55,22 @@ impl Position {
         pinned
     }
 
+    /// Return a bitboard of our pieces which block an attack by one of our
+    /// sliding pieces on the opponent king. Moving one of them off the line
+    /// gives a discovered check
+    pub(crate) fn discoverers(&self) -> BitBoard {
+        let rooks = self.us.rook | self.us.queen;
+        let bishops = self.us.bishop | self.us.queen;
+        let occ = self.occ;
+
+        let mut blockers = constants::bb::EMPTY;
+        blockers |= self.them.king.hq_file_attacks(occ) & rooks.ks_file_attacks(occ);
+        blockers |= self.them.king.hq_rank_attacks(occ) & rooks.ks_rank_attacks(occ);
+        blockers |= self.them.king.hq_diag_attacks(occ) & bishops.ks_diag_attacks(occ);
+        blockers |= self.them.king.hq_adiag_attacks(occ) & bishops.ks_adiag_attacks(occ);
+        blockers & self.us.all
+    }
+
     /// Return a bitboard with all the squares our pieces are attacking
     fn attack_sq<C: Color>(&self) -> BitBoard {
         let mut targets = constants::bb::EMPTY;
@@ -57,17 +82,32 @@ impl Position {
         targets
     }
 
-    /// Check that in the position, we cannot capture their king. If so, it's an illegal position
-    pub fn check_legal(&self) -> Result<(), ()> {
+    /// Check that each side has one king and that in the position, we cannot
+    /// capture their king. If not, it's an illegal position
+    pub(crate) fn check_legal(&self) -> Result<(), Violation> {
+        let (white, black) = self.white_black();
+        for (color, bbset) in [(ColorT::White, white), (ColorT::Black, black)] {
+            let count = bbset.king.pop_count() as u32;
+            if count != 1 {
+                return Err(Violation::KingCount { color, count });
+            }
+        }
+
+        if self.opponent_in_check() {
+            Err(Violation::OpponentInCheck)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Return true if the side to move attacks the opponent king, which
+    /// happens after a move that leaves the moving side's king in check
+    pub(crate) fn opponent_in_check(&self) -> bool {
         let attack_squares = match self.stm {
             ColorT::White => self.attack_sq::<White>(),
             ColorT::Black => self.attack_sq::<Black>(),
         };
-        if (attack_squares & self.them.king).is_not_empty() {
-            Err(())
-        } else {
-            Ok(())
-        }
+        (attack_squares & self.them.king).is_not_empty()
     }
 }
 
@@ -5,24 +5,57 @@ use types::ColorT;
 mod analysis;
 mod parse;
 pub mod states;
+mod validate;
 
-#[derive(Debug, Clone, Copy)]
+pub use parse::{FenError, FenField};
+pub use validate::Violation;
+
+/// A chess position. Making a move with [`Position::make_move`] returns a
+/// new position, leaving the original untouched, while
+/// [`Position::do_move`] and [`Position::undo_move`] modify it in place.
+///
+/// ```
+/// use rperft::Position;
+///
+/// let pos = Position::new_start_pos();
+/// assert_eq!(pos.legal_moves().count(), 20);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Position {
-    pub us: BitBoardSet,
-    pub them: BitBoardSet,
-    pub occ: BitBoard,
-    pub free: BitBoard,
-    pub castling_rights: BitBoard,
-    pub ep_sq: BitBoard,
-    pub halfmove_clock: u8,
-    pub fullmove_clock: u8,
-    pub key: u64,
-    pub wtm: bool,
-    pub stm: ColorT,
-    pub ply: u8,
+    pub(crate) us: BitBoardSet,
+    pub(crate) them: BitBoardSet,
+    pub(crate) occ: BitBoard,
+    pub(crate) free: BitBoard,
+    pub(crate) castling_rights: BitBoard,
+    pub(crate) ep_sq: BitBoard,
+    pub(crate) halfmove_clock: u8,
+    pub(crate) fullmove_clock: u8,
+    pub(crate) key: u64,
+    pub(crate) wtm: bool,
+    pub(crate) stm: ColorT,
+    pub(crate) ply: u8,
+    pub(crate) chess960: bool,
+}
+
+impl Position {
+    /// The side to move
+    pub fn side_to_move(&self) -> ColorT {
+        self.stm
+    }
+
+    /// The Polyglot Zobrist hash of the position
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    /// Whether the position uses Chess960 castling rules. Chess960 castles
+    /// are encoded and written in UCI notation as the king capturing its rook
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct BitBoardSet {
     pub all: BitBoard,
     pub pawn: BitBoard,
@@ -4,13 +4,102 @@ use super::*;
 
 use std::iter::zip;
 
-use constants::bb;
+use constants::{ascii, bb, file::*, rank::*};
 use types::PieceT;
 
+/// The fields of a FEN string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FenField {
+    Board,
+    SideToMove,
+    Castling,
+    EnPassant,
+    HalfmoveClock,
+    FullmoveClock,
+}
+
+/// Reasons a FEN string can fail to parse. Offsets are character offsets
+/// into the FEN string, after leading whitespace has been trimmed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    /// The FEN does not contain six space separated fields
+    FieldCount(usize),
+    /// The board field does not contain eight ranks
+    RankCount(usize),
+    /// An unrecognised character in the board field
+    InvalidPiece { c: char, offset: usize },
+    /// A rank describes more than eight squares
+    RankOverflow { rank: u8, offset: usize },
+    /// A rank describes fewer than eight squares
+    RankUnderflow { rank: u8, offset: usize },
+    /// A side to move, castling, en passant or clock field could not be parsed
+    InvalidField {
+        field: FenField,
+        value: String,
+        offset: usize,
+    },
+    /// A side does not have one king, or the side not to move is in check
+    IllegalPosition(Violation),
+}
+
+impl std::fmt::Display for FenField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FenField::Board => "board",
+            FenField::SideToMove => "side to move",
+            FenField::Castling => "castling",
+            FenField::EnPassant => "en passant",
+            FenField::HalfmoveClock => "halfmove clock",
+            FenField::FullmoveClock => "fullmove clock",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::FieldCount(n) => write!(f, "expected 6 fields, found {n}"),
+            FenError::RankCount(n) => write!(f, "expected 8 ranks, found {n}"),
+            FenError::InvalidPiece { c, offset } => {
+                write!(f, "invalid piece '{c}' at offset {offset}")
+            }
+            FenError::RankOverflow { rank, offset } => {
+                write!(f, "rank {rank} has more than 8 squares at offset {offset}")
+            }
+            FenError::RankUnderflow { rank, offset } => {
+                write!(f, "rank {rank} has fewer than 8 squares at offset {offset}")
+            }
+            FenError::InvalidField {
+                field,
+                value,
+                offset,
+            } => write!(f, "invalid {field} field '{value}' at offset {offset}"),
+            FenError::IllegalPosition(v) => write!(f, "illegal position: {v}"),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
 impl Position {
     /// Parse a FEN string into a position representation
-    pub fn from_fen(fen: &str) -> Result<Self, ()> {
-        let tokens: Vec<&str> = fen.trim().split(" ").collect();
+    ///
+    /// ```
+    /// use rperft::{FenError, Position};
+    ///
+    /// let pos = Position::from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap();
+    /// assert_eq!(pos.legal_moves().count(), 14);
+    /// assert_eq!(Position::from_fen("not a fen").unwrap_err(), FenError::FieldCount(3));
+    /// ```
+    pub fn from_fen(fen: &str) -> Result<Self, FenError> {
+        // Pair each token with the character offset at which it starts
+        let mut tokens = Vec::new();
+        let mut offset = 0;
+        for token in fen.trim().split(' ') {
+            tokens.push((offset, token));
+            offset += token.chars().count() + 1;
+        }
 
         /* FEN strings contain 6 tokens representing
             1. Board
@@ -21,103 +110,149 @@ impl Position {
             6. Fullmove clock
         */
         if tokens.len() != 6 {
-            return Err(());
+            return Err(FenError::FieldCount(tokens.len()));
         }
 
+        let invalid_field = |field: FenField, i: usize| FenError::InvalidField {
+            field,
+            value: tokens[i].1.to_string(),
+            offset: tokens[i].0,
+        };
+
         // Fill BBSet for white and black. Set 'us' as white for now
         let mut us = BitBoardSet::default();
         let mut them = BitBoardSet::default();
-        let mut board_tokens: Vec<&str> = tokens[0].split("/").collect();
+        let board_tokens: Vec<&str> = tokens[0].1.split('/').collect();
 
         if board_tokens.len() != 8 {
-            return Err(());
+            return Err(FenError::RankCount(board_tokens.len()));
         }
 
-        // Reverse vector so index 0 is at square A1
-        board_tokens.reverse();
-        let board = board_tokens.join("");
-        let mut sq = 0;
-
-        for c in board.chars() {
-            if sq >= 64 {
-                return Err(());
-            }
-            let mask = BitBoard::from_sq(sq);
-
-            // Alphabetic characters represent a piece of the square
-            if c.is_alphabetic() {
-                let bbset = if c.is_uppercase() { &mut us } else { &mut them };
-                bbset.all |= mask;
-                match c {
-                    'p' | 'P' => bbset.pawn |= mask,
-                    'r' | 'R' => bbset.rook |= mask,
-                    'n' | 'N' => bbset.knight |= mask,
-                    'b' | 'B' => bbset.bishop |= mask,
-                    'q' | 'Q' => bbset.queen |= mask,
-                    'k' | 'K' => bbset.king |= mask,
-                    _ => return Err(()),
+        // Ranks are listed from the 8th rank to the 1st
+        let mut offset = tokens[0].0;
+        for (rank, rank_token) in zip((1..=8).rev(), board_tokens) {
+            let mut file = 0;
+
+            for c in rank_token.chars() {
+                // Alphabetic characters represent a piece of the square
+                if c.is_alphabetic() {
+                    if file >= 8 {
+                        return Err(FenError::RankOverflow { rank, offset });
+                    }
+                    let mask = BitBoard::from_sq((rank as usize - 1) * 8 + file);
+                    let bbset = if c.is_uppercase() { &mut us } else { &mut them };
+                    bbset.all |= mask;
+                    match c {
+                        'p' | 'P' => bbset.pawn |= mask,
+                        'r' | 'R' => bbset.rook |= mask,
+                        'n' | 'N' => bbset.knight |= mask,
+                        'b' | 'B' => bbset.bishop |= mask,
+                        'q' | 'Q' => bbset.queen |= mask,
+                        'k' | 'K' => bbset.king |= mask,
+                        _ => return Err(FenError::InvalidPiece { c, offset }),
+                    }
+                    file += 1;
                 }
-                sq += 1;
-            }
-            // Numeric pieces represent empty squares
-            else if c.is_numeric() {
-                let n_empty = c.to_digit(10).expect("carried out .is_numeric check");
-                sq += n_empty as usize;
-            }
-            // Non-alphanumeric characters are invalid
-            else {
-                return Err(());
+                // Numeric pieces represent empty squares
+                else if let Some(n_empty) = c.to_digit(10) {
+                    file += n_empty as usize;
+                    if file > 8 {
+                        return Err(FenError::RankOverflow { rank, offset });
+                    }
+                }
+                // Non-alphanumeric characters are invalid
+                else {
+                    return Err(FenError::InvalidPiece { c, offset });
+                }
+                offset += 1;
             }
-        }
 
-        // All 64 squares must be accounted for
-        if sq != 64 {
-            return Err(());
+            // All 8 squares of the rank must be accounted for
+            if file != 8 {
+                return Err(FenError::RankUnderflow { rank, offset });
+            }
+            // Skip the '/' separator
+            offset += 1;
         }
 
         let occ = us.all | them.all;
         let free = !occ;
 
         // Set side to move
-        let (wtm, stm) = match tokens[1] {
+        let (wtm, stm) = match tokens[1].1 {
             "w" => (true, ColorT::White),
             "b" => (false, ColorT::Black),
-            _ => return Err(()),
+            _ => return Err(invalid_field(FenField::SideToMove, 1)),
         };
 
-        // Set castling rights
+        // Set castling rights, stored as the squares of the castling rooks.
+        // K and Q refer to the outermost rook on either side of the king
+        // (X-FEN), and rook file letters are accepted (Shredder-FEN)
         let mut castling_rights = bb::EMPTY;
-        for c in tokens[2].chars() {
-            match c {
-                'K' => castling_rights |= bb::H1,
-                'k' => castling_rights |= bb::H8,
-                'Q' => castling_rights |= bb::A1,
-                'q' => castling_rights |= bb::A8,
-                '-' => (),
-                _ => return Err(()),
+        let mut chess960 = false;
+        if tokens[2].1 != "-" {
+            for (i, c) in tokens[2].1.chars().enumerate() {
+                let (bbset, rank) = if c.is_uppercase() {
+                    (&us, RANK_1)
+                } else {
+                    (&them, RANK_8)
+                };
+                let (rooks, king) = (bbset.rook & rank, bbset.king & rank);
+                castling_rights |= match c.to_ascii_lowercase() {
+                    'k' => outermost_rook(rooks, king, true).unwrap_or(rank & FILE_H),
+                    'q' => outermost_rook(rooks, king, false).unwrap_or(rank & FILE_A),
+                    file @ 'a'..='h' => {
+                        chess960 = true;
+                        rank & FILE_MASKS[file as usize - ascii::LOWER_A]
+                    }
+                    _ => {
+                        return Err(FenError::InvalidField {
+                            field: FenField::Castling,
+                            value: tokens[2].1.to_string(),
+                            offset: tokens[2].0 + i,
+                        })
+                    }
+                };
             }
         }
 
-        // Set en passant target square
-        let ep_sq = if tokens[3] == "-" {
+        // Castling with a rook outside the corners or a king outside the e
+        // file can only be Chess960
+        for (bbset, rank, king_sq) in [(&us, RANK_1, bb::E1), (&them, RANK_8, bb::E8)] {
+            let rights = castling_rights & rank;
+            if rights.is_not_empty()
+                && ((rights & !(FILE_A | FILE_H)).is_not_empty()
+                    || (bbset.king & king_sq).is_empty())
+            {
+                chess960 = true;
+            }
+        }
+
+        // Set en passant target square, which must be behind a pawn that has
+        // just double pushed
+        let ep_sq = if tokens[3].1 == "-" {
             bb::EMPTY
         } else {
-            match BitBoard::from_algebraic(tokens[3]) {
-                Ok(bb) => bb,
-                Err(_) => return Err(()),
+            let ep_rank = match stm {
+                ColorT::White => constants::rank::RANK_6,
+                ColorT::Black => constants::rank::RANK_3,
+            };
+            match BitBoard::from_algebraic(tokens[3].1) {
+                Some(bb) if (bb & ep_rank).is_not_empty() => bb,
+                _ => return Err(invalid_field(FenField::EnPassant, 3)),
             }
         };
 
         // Set halfmove clock
-        let halfmove_clock = match tokens[4].parse::<u8>() {
+        let halfmove_clock = match tokens[4].1.parse::<u8>() {
             Ok(val) => val,
-            Err(_) => return Err(()),
+            Err(_) => return Err(invalid_field(FenField::HalfmoveClock, 4)),
         };
 
         // Set fullmove clock
-        let fullmove_clock = match tokens[5].parse::<u8>() {
+        let fullmove_clock = match tokens[5].1.parse::<u8>() {
             Ok(val) => val,
-            Err(_) => return Err(()),
+            Err(_) => return Err(invalid_field(FenField::FullmoveClock, 5)),
         };
 
         // Swap us/them pointers if black to move
@@ -138,12 +273,15 @@ impl Position {
             wtm,
             stm,
             ply: 0,
+            chess960,
         };
 
         // Initialize Zobrist key
         pos.key = pos.generate_zobrist_key();
-        // Check that the king cannot be captured
-        pos.check_legal()?;
+        // Check that there is one king each and the king cannot be captured
+        if let Err(v) = pos.check_legal() {
+            return Err(FenError::IllegalPosition(v));
+        }
         return Ok(pos);
     }
 
@@ -178,7 +316,34 @@ impl Position {
     }
 
     /// Generate the FEN string of the position
+    ///
+    /// ```
+    /// use rperft::Position;
+    ///
+    /// let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    /// assert_eq!(Position::from_fen(fen).unwrap().to_fen(), fen);
+    /// ```
     pub fn to_fen(&self) -> String {
+        self.fen_string(false)
+    }
+
+    /// Generate the Shredder-FEN string of the position, where castling
+    /// rights are given by the files of the castling rooks
+    ///
+    /// ```
+    /// use rperft::Position;
+    ///
+    /// let pos = Position::new_start_pos();
+    /// assert_eq!(
+    ///     pos.to_shredder_fen(),
+    ///     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+    /// );
+    /// ```
+    pub fn to_shredder_fen(&self) -> String {
+        self.fen_string(true)
+    }
+
+    fn fen_string(&self, shredder: bool) -> String {
         let mut tokens = Vec::new();
         let mut array = self.to_array();
         // Reverse for FEN parsing
@@ -215,18 +380,7 @@ impl Position {
             ColorT::Black => tokens.push("b".to_string()),
         }
 
-        // Build castling token
-        let mut castling_token = String::new();
-        for (target_sq, c) in std::iter::zip([bb::H1, bb::A1, bb::H8, bb::A8], ['K', 'Q', 'k', 'q'])
-        {
-            if self.castling_rights & target_sq != bb::EMPTY {
-                castling_token.push(c)
-            }
-        }
-        if castling_token.len() == 0 {
-            castling_token.push('-')
-        }
-        tokens.push(castling_token);
+        tokens.push(self.castling_token(shredder));
 
         // Push en passant token
         if self.ep_sq != bb::EMPTY {
@@ -242,6 +396,39 @@ impl Position {
         return tokens.join(" ");
     }
 
+    /// Build the castling token, kingside rights first. Chess960 positions
+    /// use X-FEN, which falls back to the rook file when the castling rook is
+    /// not the outermost rook on its side. Shredder-FEN always uses the file
+    fn castling_token(&self, shredder: bool) -> String {
+        let mut token = String::new();
+        let (white, black) = self.white_black();
+        for (bbset, rank) in [(white, RANK_1), (black, RANK_8)] {
+            let king_file = bbset.king.get_ls1b_index() % 8;
+            let mut rights = (self.castling_rights & rank).forward_scan();
+            rights.reverse();
+            for rook in rights {
+                let kingside = rook.to_sq() % 8 > king_file;
+                let outermost = outermost_rook(bbset.rook & rank, bbset.king & rank, kingside);
+                let c = if shredder || (self.chess960 && outermost != Some(rook)) {
+                    (ascii::LOWER_A + rook.to_sq() % 8) as u8 as char
+                } else if kingside {
+                    'k'
+                } else {
+                    'q'
+                };
+                if rank == RANK_1 {
+                    token.push(c.to_ascii_uppercase())
+                } else {
+                    token.push(c)
+                }
+            }
+        }
+        if token.is_empty() {
+            token.push('-')
+        }
+        token
+    }
+
     /// Convert to string representation to visually display the board
     pub fn to_board(&self) -> String {
         let array = self.to_array();
@@ -269,6 +456,21 @@ impl Position {
     }
 }
 
+/// Find the outermost rook on the kingside or queenside of a king on the back
+/// rank, if any
+fn outermost_rook(rooks: BitBoard, king: BitBoard, kingside: bool) -> Option<BitBoard> {
+    if king.is_empty() {
+        return None;
+    }
+    let king_sq = king.get_ls1b_index();
+    let mut candidates = rooks.filter(|rook| (rook.to_sq() > king_sq) == kingside);
+    if kingside {
+        candidates.last()
+    } else {
+        candidates.next()
+    }
+}
+
 impl std::fmt::Display for Position {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -328,7 +530,8 @@ impl std::ops::IndexMut<PieceT> for BitBoardSet {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use constants::rank::*;
+
+    use test_case::test_case;
 
     #[test]
     fn test_start_pos() {
@@ -372,6 +575,65 @@ mod tests {
         assert_eq!(pos.to_fen(), constants::fen::TEST_3)
     }
 
+    #[test_case(constants::fen::STARTING_FEN,
+        constants::fen::STARTING_FEN,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1", false; "standard")]
+    #[test_case("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9",
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", true; "shredder")]
+    #[test_case("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w kq - 0 9",
+        "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w kq - 0 9",
+        "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", true; "x-fen")]
+    #[test_case("1r2k1rr/8/8/8/8/8/8/1R2K1RR w GBgb - 0 1",
+        "1r2k1rr/8/8/8/8/8/8/1R2K1RR w GQgq - 0 1",
+        "1r2k1rr/8/8/8/8/8/8/1R2K1RR w GBgb - 0 1", true; "inner rook")]
+    fn test_chess960_fen(fen: &str, x_fen: &str, shredder_fen: &str, chess960: bool) {
+        let pos = Position::from_fen(fen).unwrap();
+        assert_eq!(pos.is_chess960(), chess960);
+        assert_eq!(pos.to_fen(), x_fen);
+        assert_eq!(pos.to_shredder_fen(), shredder_fen);
+        assert_eq!(
+            Position::from_fen(x_fen).unwrap().castling_rights,
+            pos.castling_rights
+        );
+        assert_eq!(
+            Position::from_fen(shredder_fen).unwrap().castling_rights,
+            pos.castling_rights
+        );
+    }
+
+    #[test_case("8/8/8/8/8/8/8/8 w - -", FenError::FieldCount(4); "too few fields")]
+    #[test_case("8/8/8/8/8/8/8 w - - 0 1", FenError::RankCount(7); "too few ranks")]
+    #[test_case("rnbqkbnr/ppxppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        FenError::InvalidPiece { c: 'x', offset: 11 }; "bad piece")]
+    #[test_case("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        FenError::RankOverflow { rank: 6, offset: 18 }; "rank overflow")]
+    #[test_case("rnbqkbnrp/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        FenError::RankOverflow { rank: 8, offset: 8 }; "rank overflow piece")]
+    #[test_case("rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        FenError::RankUnderflow { rank: 7, offset: 16 }; "rank underflow")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+        FenError::InvalidField { field: FenField::SideToMove, value: "x".into(), offset: 44 }; "bad side")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq - 0 1",
+        FenError::InvalidField { field: FenField::Castling, value: "KQxq".into(), offset: 48 }; "bad castling")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1",
+        FenError::InvalidField { field: FenField::EnPassant, value: "e3".into(), offset: 51 }; "bad ep rank")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq i6 0 1",
+        FenError::InvalidField { field: FenField::EnPassant, value: "i6".into(), offset: 51 }; "bad ep file")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - -1 1",
+        FenError::InvalidField { field: FenField::HalfmoveClock, value: "-1".into(), offset: 53 }; "bad halfmove")]
+    #[test_case("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 x",
+        FenError::InvalidField { field: FenField::FullmoveClock, value: "x".into(), offset: 55 }; "bad fullmove")]
+    #[test_case("4k2R/8/8/8/8/8/8/4K3 w - - 0 1",
+        FenError::IllegalPosition(Violation::OpponentInCheck); "king capturable")]
+    #[test_case("4k3/8/8/8/8/8/8/8 w - - 0 1",
+        FenError::IllegalPosition(Violation::KingCount { color: ColorT::White, count: 0 }); "missing king")]
+    #[test_case("4k3/8/8/8/8/8/8/3KK3 b - - 0 1",
+        FenError::IllegalPosition(Violation::KingCount { color: ColorT::White, count: 2 }); "two kings")]
+    fn test_from_fen_error(fen: &str, expected: FenError) {
+        assert_eq!(Position::from_fen(fen).unwrap_err(), expected)
+    }
+
     #[ignore]
     #[test]
     // Run manually and inspect
@@ -0,0 +1,288 @@
+/// Deep validation of a position, for positions from untrusted sources
+use super::*;
+
+use constants::{bb, rank::*};
+use states::*;
+use types::ColorT;
+
+/// A way in which a position breaks the rules of chess or could not have
+/// been reached from the starting position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// A side does not have exactly one king
+    KingCount { color: ColorT, count: u32 },
+    /// The side not to move is in check
+    OpponentInCheck,
+    /// Pawns on the first or eighth rank
+    PawnsOnBackRank(BitBoard),
+    /// A castling right without the king and rook on their starting squares
+    CastlingRights { color: ColorT, rook_sq: BitBoard },
+    /// An en passant square which is occupied or not behind a pawn which
+    /// has just double pushed
+    EnPassant(BitBoard),
+    /// More than two pieces give check
+    TooManyCheckers(u32),
+    /// The checking pieces could not have been produced by a single move
+    ImpossibleCheck(BitBoard),
+    /// A side has more than sixteen pieces
+    TooManyPieces { color: ColorT, count: u32 },
+    /// A side has more than eight pawns
+    TooManyPawns { color: ColorT, count: u32 },
+    /// A side has more promoted pieces than missing pawns
+    TooManyPromotedPieces { color: ColorT },
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::KingCount { color, count } => {
+                write!(f, "{color:?} has {count} kings")
+            }
+            Violation::OpponentInCheck => write!(f, "the side not to move is in check"),
+            Violation::PawnsOnBackRank(_) => write!(f, "pawns on the back rank"),
+            Violation::CastlingRights { color, rook_sq } => write!(
+                f,
+                "{color:?} has the right to castle with a rook on {} but the king or rook has moved",
+                rook_sq.to_algebraic()
+            ),
+            Violation::EnPassant(sq) => {
+                write!(f, "impossible en passant square {}", sq.to_algebraic())
+            }
+            Violation::TooManyCheckers(n) => write!(f, "{n} pieces give check"),
+            Violation::ImpossibleCheck(_) => write!(f, "impossible check"),
+            Violation::TooManyPieces { color, count } => {
+                write!(f, "{color:?} has {count} pieces")
+            }
+            Violation::TooManyPawns { color, count } => {
+                write!(f, "{color:?} has {count} pawns")
+            }
+            Violation::TooManyPromotedPieces { color } => {
+                write!(f, "{color:?} has more promoted pieces than missing pawns")
+            }
+        }
+    }
+}
+
+impl Position {
+    /// Check the position for any rule violations. Returns an empty list if
+    /// the position is valid
+    ///
+    /// ```
+    /// use rperft::{Position, Violation};
+    ///
+    /// let pos = Position::from_fen("4k3/8/8/8/8/8/8/4K3 w K - 0 1").unwrap();
+    /// assert_eq!(pos.validate().len(), 1);
+    /// assert!(matches!(pos.validate()[0], Violation::CastlingRights { .. }));
+    /// ```
+    pub fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        // The remaining checks assume there is one king for each side
+        if let Err(v) = self.check_legal() {
+            violations.push(v);
+            if let Violation::KingCount { .. } = v {
+                return violations;
+            }
+        }
+
+        let (white, black) = self.white_black();
+        for (color, bbset) in [(ColorT::White, white), (ColorT::Black, black)] {
+            violations.extend(material_violations(color, bbset));
+        }
+
+        let pawns = self.us.pawn | self.them.pawn;
+        if (pawns & (RANK_1 | RANK_8)).is_not_empty() {
+            violations.push(Violation::PawnsOnBackRank(pawns & (RANK_1 | RANK_8)));
+        }
+
+        violations.extend(self.castling_violations());
+
+        let (ep_violation, checkers_violation) = match self.stm {
+            ColorT::White => (
+                self.ep_violation::<White>(),
+                self.check_violation::<White>(),
+            ),
+            ColorT::Black => (
+                self.ep_violation::<Black>(),
+                self.check_violation::<Black>(),
+            ),
+        };
+        violations.extend(ep_violation);
+        violations.extend(checkers_violation);
+        violations
+    }
+
+    /// Castling rights require the king and rook to be on their start squares.
+    /// In Chess960 the king may start anywhere on the back rank
+    fn castling_violations(&self) -> Vec<Violation> {
+        let (white, black) = self.white_black();
+        let mut violations = Vec::new();
+        for (color, bbset, king_sq, back_rank) in [
+            (ColorT::White, white, bb::E1, RANK_1),
+            (ColorT::Black, black, bb::E8, RANK_8),
+        ] {
+            let king_sq = if self.chess960 { back_rank } else { king_sq };
+            for rook_sq in self.castling_rights & back_rank {
+                if (bbset.king & king_sq).is_empty() || (bbset.rook & rook_sq).is_empty() {
+                    violations.push(Violation::CastlingRights { color, rook_sq })
+                }
+            }
+        }
+        violations
+    }
+
+    /// The en passant square must be empty, with the square the pawn was
+    /// pushed from empty and their pawn directly in front of it
+    fn ep_violation<C: Color>(&self) -> Option<Violation> {
+        if self.ep_sq.is_empty() {
+            return None;
+        }
+        let origin = C::push_one(self.ep_sq);
+        let pushed = C::back_one(self.ep_sq);
+        if (self.ep_sq & C::push_one(C::rank_5())).is_empty()
+            || ((self.ep_sq | origin) & self.occ).is_not_empty()
+            || (pushed & self.them.pawn).is_empty()
+        {
+            return Some(Violation::EnPassant(self.ep_sq));
+        }
+        None
+    }
+
+    /// A single move can give check with at most two pieces: the moved piece
+    /// and a slider it uncovers
+    fn check_violation<C: Color>(&self) -> Option<Violation> {
+        let checkers = self.checkers::<C>();
+        let n_checkers = checkers.pop_count() as u32;
+
+        if n_checkers > 2 {
+            return Some(Violation::TooManyCheckers(n_checkers));
+        }
+
+        if n_checkers == 2 {
+            let sliders = self.them.rook | self.them.bishop | self.them.queen;
+            let a = checkers.get_ls1b();
+            let b = checkers ^ a;
+            // At least one checker must be a slider which has been uncovered,
+            // and the two checkers cannot lie on a line through the king
+            if (checkers & sliders).is_empty() || (a.between_bb(b) & self.us.king).is_not_empty() {
+                return Some(Violation::ImpossibleCheck(checkers));
+            }
+        }
+
+        // After a double push, the check must be given by the pushed pawn or
+        // a slider uncovered by the pawn leaving its start square
+        if n_checkers > 0 && self.ep_sq.is_not_empty() {
+            let origin = C::push_one(self.ep_sq);
+            let pushed = C::back_one(self.ep_sq);
+            for checker in checkers {
+                if checker != pushed && (self.us.king.between_bb(checker) & origin).is_empty() {
+                    return Some(Violation::ImpossibleCheck(checkers));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Check a side's material could have arisen from the starting material
+fn material_violations(color: ColorT, bbset: &BitBoardSet) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    let count = bbset.all.pop_count() as u32;
+    if count > 16 {
+        violations.push(Violation::TooManyPieces { color, count });
+    }
+
+    let n_pawns = bbset.pawn.pop_count() as u32;
+    if n_pawns > 8 {
+        violations.push(Violation::TooManyPawns {
+            color,
+            count: n_pawns,
+        });
+    }
+
+    // Pieces in excess of the starting material must have been promoted
+    let excess = |bb: BitBoard, n_start: i16| 0.max(bb.pop_count() - n_start) as u32;
+    let n_promoted = excess(bbset.queen, 1)
+        + excess(bbset.rook, 2)
+        + excess(bbset.bishop, 2)
+        + excess(bbset.knight, 2);
+    if n_pawns + n_promoted > 8 {
+        violations.push(Violation::TooManyPromotedPieces { color });
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    #[test_case(constants::fen::STARTING_FEN; "startpos")]
+    #[test_case(constants::fen::TEST_2; "testpos2")]
+    #[test_case(constants::fen::TEST_3; "testpos3")]
+    #[test_case(constants::fen::TEST_4; "testpos4")]
+    #[test_case(constants::fen::TEST_5; "testpos5")]
+    #[test_case(constants::fen::TEST_6; "testpos6")]
+    #[test_case("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1"; "ep square")]
+    #[test_case("8/8/8/3k4/4P3/8/8/4K3 b - e3 0 1"; "check by pushed pawn")]
+    #[test_case("8/8/8/6k1/3P4/8/8/2B1K3 b - d3 0 1"; "check uncovered by pushed pawn")]
+    #[test_case("4k3/8/8/1B6/8/8/8/4R1K1 b - - 0 1"; "rook and bishop check")]
+    #[test_case("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1"; "chess960 castling")]
+    fn test_valid(fen: &str) {
+        let pos = Position::from_fen(fen).unwrap();
+        assert_eq!(pos.validate(), vec![])
+    }
+
+    #[test_case("P3k3/8/8/8/8/8/8/4K3 b - - 0 1",
+        Violation::PawnsOnBackRank(bb::A8); "pawn on back rank")]
+    #[test_case("4k3/8/8/8/8/8/8/4K2R w Kq - 0 1",
+        Violation::CastlingRights { color: ColorT::Black, rook_sq: bb::A8 }; "castling without rook")]
+    #[test_case("4k3/8/8/8/8/8/3K4/7R w K - 0 1",
+        Violation::CastlingRights { color: ColorT::White, rook_sq: bb::H1 }; "castling without king")]
+    #[test_case("4k3/8/8/8/8/8/8/1K5R w D - 0 1",
+        Violation::CastlingRights { color: ColorT::White, rook_sq: bb::D1 }; "chess960 castling without rook")]
+    #[test_case("4k3/8/8/8/8/8/8/4K3 w - e6 0 1",
+        Violation::EnPassant(bb::E6); "ep without pawn")]
+    #[test_case("4k3/4p3/8/4p3/8/8/8/4K3 w - e6 0 1",
+        Violation::EnPassant(bb::E6); "ep origin occupied")]
+    #[test_case("4k3/8/8/8/8/5n2/3p4/4K3 w - - 0 1",
+        Violation::ImpossibleCheck(bb::D2 | bb::F3); "knight and pawn check")]
+    #[test_case("4k3/8/8/8/8/8/8/r3K2r w - - 0 1",
+        Violation::ImpossibleCheck(bb::A1 | bb::H1); "checkers either side of king")]
+    #[test_case("4k3/8/8/3pP3/8/8/8/4K2r w - d6 0 1",
+        Violation::ImpossibleCheck(bb::H1); "check not from double push")]
+    #[test_case("4k3/8/8/8/8/3n1n2/2n5/4K3 w - - 0 1",
+        Violation::TooManyCheckers(3); "too many checkers")]
+    #[test_case("4k3/pppppppp/p7/8/8/8/8/4K3 w - - 0 1",
+        Violation::TooManyPawns { color: ColorT::Black, count: 9 }; "too many pawns")]
+    #[test_case("3qk3/pppppppp/7q/8/8/8/8/4K3 w - - 0 1",
+        Violation::TooManyPromotedPieces { color: ColorT::Black }; "too many promoted pieces")]
+    fn test_violation(fen: &str, expected: Violation) {
+        let pos = Position::from_fen(fen).unwrap();
+        assert!(
+            pos.validate().contains(&expected),
+            "{:?} not in {:?}",
+            expected,
+            pos.validate()
+        )
+    }
+
+    #[test]
+    fn test_opponent_in_check() {
+        let mut pos = Position::from_fen("4k3/8/8/8/8/8/8/4K2r w - - 0 1").unwrap();
+        pos.change_state();
+        assert_eq!(pos.validate(), vec![Violation::OpponentInCheck])
+    }
+
+    #[test]
+    fn test_too_many_pieces() {
+        let fen = "rnbqkbnr/pppppppp/n7/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let pos = Position::from_fen(fen).unwrap();
+        assert!(pos.validate().contains(&Violation::TooManyPieces {
+            color: ColorT::Black,
+            count: 17
+        }))
+    }
+}
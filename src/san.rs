@@ -0,0 +1,346 @@
+/// Standard Algebraic Notation (SAN) formatting, and parsing of SAN and UCI
+/// moves against the legal moves of a position
+use super::*;
+
+use movegen::generate_all;
+use movelist::{MoveCounter, MoveVec};
+use mv::Move;
+use position::Position;
+use types::{MoveT, PieceT};
+
+/// Error returned when a move string cannot be resolved to a legal move
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveParseError {
+    /// The string is not a move in SAN or UCI notation
+    Invalid(String),
+    /// No legal move matches the string
+    Illegal(String),
+    /// Several legal moves match the string, given in UCI notation
+    Ambiguous {
+        san: String,
+        candidates: Vec<String>,
+    },
+}
+
+impl std::fmt::Display for MoveParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveParseError::Invalid(s) => write!(f, "'{s}' is not a move in SAN or UCI notation"),
+            MoveParseError::Illegal(s) => write!(f, "'{s}' is not a legal move in the position"),
+            MoveParseError::Ambiguous { san, candidates } => {
+                write!(f, "'{san}' is ambiguous between {}", candidates.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for MoveParseError {}
+
+impl Position {
+    /// Write a legal move in Standard Algebraic Notation, e.g. Nbd7, exd6,
+    /// e8=Q+ or O-O-O#
+    ///
+    /// ```
+    /// use rperft::Position;
+    ///
+    /// let pos = Position::from_fen(
+    ///     "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    /// ).unwrap();
+    /// let san: Vec<String> = pos.legal_moves().map(|mv| pos.to_san(&mv)).collect();
+    /// assert!(san.contains(&"Bxa6".to_string()));
+    /// assert!(san.contains(&"Nxf7".to_string()));
+    /// assert!(san.contains(&"O-O-O".to_string()));
+    /// ```
+    pub fn to_san(&self, mv: &Move) -> String {
+        let mut san = match mv.mt() {
+            MoveT::KSCastle => "O-O".to_string(),
+            MoveT::QSCastle => "O-O-O".to_string(),
+            _ => self.san_body(mv),
+        };
+
+        let new_pos = self.make_move(mv);
+        if new_pos.stm_checkers().is_not_empty() {
+            let mut replies = MoveCounter::default();
+            generate_all(&new_pos, &mut replies);
+            san.push(if replies.nodes == 0 { '#' } else { '+' });
+        }
+        san
+    }
+
+    /// The piece, disambiguation, capture, target and promotion of a move
+    fn san_body(&self, mv: &Move) -> String {
+        let (from, to) = (mv.from(), mv.to());
+        let pt = self.us.pt_at(from).expect("is occupied");
+        let mut san = String::new();
+
+        if let PieceT::Pawn = pt {
+            if mv.is_capture() {
+                san.push_str(&from.to_algebraic()[..1]);
+            }
+        } else {
+            san.push(piece_char(pt));
+            // Other pieces of the same type which can move to the same square
+            let mut moves = MoveVec::new();
+            generate_all(self, &mut moves);
+            let others: Vec<BitBoard> = moves
+                .iter()
+                .filter(|other| other.to() == to && other.from() != from)
+                .map(|other| other.from())
+                .filter(|&other| self.us.pt_at(other) == Some(pt))
+                .collect();
+
+            if !others.is_empty() {
+                let square = from.to_algebraic();
+                let same_file = others.iter().any(|o| o.to_sq() % 8 == from.to_sq() % 8);
+                let same_rank = others.iter().any(|o| o.to_sq() / 8 == from.to_sq() / 8);
+                if !same_file {
+                    san.push_str(&square[..1]);
+                } else if !same_rank {
+                    san.push_str(&square[1..]);
+                } else {
+                    san.push_str(&square);
+                }
+            }
+        }
+
+        if mv.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&to.to_algebraic());
+
+        if mv.is_promo() {
+            san.push('=');
+            san.push(piece_char(mv.promo_pt()));
+        }
+        san
+    }
+
+    /// Resolve a move in SAN or UCI notation against the legal moves of the
+    /// position. Check, mate and annotation suffixes are ignored, and
+    /// castles may be written with zeros
+    ///
+    /// ```
+    /// use rperft::{MoveParseError, Position};
+    ///
+    /// let pos = Position::new_start_pos();
+    /// assert_eq!(pos.parse_move("Nf3").unwrap().to_algebraic(), "g1f3");
+    /// assert_eq!(pos.parse_move("e2e4").unwrap().to_algebraic(), "e2e4");
+    /// assert!(matches!(pos.parse_move("Ke2"), Err(MoveParseError::Illegal(_))));
+    /// ```
+    pub fn parse_move(&self, s: &str) -> Result<Move, MoveParseError> {
+        let mut moves = MoveVec::new();
+        generate_all(self, &mut moves);
+
+        if let Some(mv) = moves.iter().find(|mv| mv.to_algebraic() == s) {
+            return Ok(*mv);
+        }
+
+        let san = s.trim_end_matches(['+', '#', '!', '?']);
+        let candidates: Vec<Move> = match san {
+            "O-O" | "0-0" => moves
+                .iter()
+                .filter(|mv| matches!(mv.mt(), MoveT::KSCastle))
+                .copied()
+                .collect(),
+            "O-O-O" | "0-0-0" => moves
+                .iter()
+                .filter(|mv| matches!(mv.mt(), MoveT::QSCastle))
+                .copied()
+                .collect(),
+            _ => {
+                let pattern =
+                    SanPattern::parse(san).ok_or(MoveParseError::Invalid(s.to_string()))?;
+                moves
+                    .iter()
+                    .filter(|mv| pattern.matches(self, mv))
+                    .copied()
+                    .collect()
+            }
+        };
+
+        match candidates.len() {
+            0 => Err(MoveParseError::Illegal(s.to_string())),
+            1 => Ok(candidates[0]),
+            _ => Err(MoveParseError::Ambiguous {
+                san: s.to_string(),
+                candidates: candidates.iter().map(|mv| mv.to_algebraic()).collect(),
+            }),
+        }
+    }
+}
+
+/// The parts of a non-castling SAN move
+struct SanPattern {
+    pt: PieceT,
+    from_file: Option<usize>,
+    from_rank: Option<usize>,
+    to: BitBoard,
+    promo_pt: Option<PieceT>,
+}
+
+impl SanPattern {
+    fn parse(san: &str) -> Option<Self> {
+        let mut chars: Vec<char> = san.chars().collect();
+
+        // Piece letters are upper case, so a leading b is always a file
+        let pt = match chars.first().and_then(|&c| char_piece(c)) {
+            Some(pt) => {
+                chars.remove(0);
+                pt
+            }
+            None => PieceT::Pawn,
+        };
+
+        let promo_pt = match chars.iter().rposition(|&c| c == '=') {
+            Some(i) if i + 2 == chars.len() => {
+                let promo_pt = char_piece(chars[i + 1])?;
+                chars.truncate(i);
+                Some(promo_pt)
+            }
+            Some(_) => return None,
+            None => match chars.last().and_then(|&c| char_piece(c)) {
+                // Also accept promotions without the equals sign, e.g. e8Q
+                Some(promo_pt) if pt == PieceT::Pawn => {
+                    chars.pop();
+                    Some(promo_pt)
+                }
+                _ => None,
+            },
+        };
+
+        if chars.len() < 2 {
+            return None;
+        }
+        let to_chars = chars.split_off(chars.len() - 2);
+        let to = square(to_chars[0], to_chars[1])?;
+
+        // Whatever remains is disambiguation, and an optional capture marker
+        if chars.last() == Some(&'x') {
+            chars.pop();
+        }
+        let (mut from_file, mut from_rank) = (None, None);
+        for c in chars {
+            match c {
+                'a'..='h' if from_file.is_none() && from_rank.is_none() => {
+                    from_file = Some(c as usize - 'a' as usize)
+                }
+                '1'..='8' if from_rank.is_none() => from_rank = Some(c as usize - '1' as usize),
+                _ => return None,
+            }
+        }
+
+        Some(Self {
+            pt,
+            from_file,
+            from_rank,
+            to,
+            promo_pt,
+        })
+    }
+
+    fn matches(&self, pos: &Position, mv: &Move) -> bool {
+        let from = mv.from().to_sq();
+        let promo_pt = if mv.is_promo() {
+            Some(mv.promo_pt())
+        } else {
+            None
+        };
+        !matches!(mv.mt(), MoveT::KSCastle | MoveT::QSCastle)
+            && mv.to() == self.to
+            && pos.us.pt_at(mv.from()) == Some(self.pt)
+            && self.from_file.is_none_or(|file| from % 8 == file)
+            && self.from_rank.is_none_or(|rank| from / 8 == rank)
+            && promo_pt == self.promo_pt
+    }
+}
+
+fn square(file: char, rank: char) -> Option<BitBoard> {
+    if !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+        return None;
+    }
+    let sq = (rank as usize - '1' as usize) * 8 + (file as usize - 'a' as usize);
+    Some(BitBoard::from_sq(sq))
+}
+
+fn piece_char(pt: PieceT) -> char {
+    match pt {
+        PieceT::Pawn => 'P',
+        PieceT::Knight => 'N',
+        PieceT::Bishop => 'B',
+        PieceT::Rook => 'R',
+        PieceT::Queen => 'Q',
+        PieceT::King => 'K',
+        PieceT::Any => unreachable!("moves are made by a piece"),
+    }
+}
+
+fn char_piece(c: char) -> Option<PieceT> {
+    match c {
+        'N' => Some(PieceT::Knight),
+        'B' => Some(PieceT::Bishop),
+        'R' => Some(PieceT::Rook),
+        'Q' => Some(PieceT::Queen),
+        'K' => Some(PieceT::King),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    #[test_case(constants::fen::STARTING_FEN, "g1f3", "Nf3"; "knight move")]
+    #[test_case(constants::fen::TEST_2, "e2a6", "Bxa6"; "capture")]
+    #[test_case(constants::fen::TEST_2, "e1g1", "O-O"; "kingside castle")]
+    #[test_case(constants::fen::TEST_2, "e1c1", "O-O-O"; "queenside castle")]
+    #[test_case(constants::fen::TEST_2, "d5e6", "dxe6"; "pawn capture")]
+    #[test_case(constants::fen::TEST_2, "c3b1", "Nb1"; "knight not ambiguous")]
+    #[test_case(constants::fen::TEST_2, "e5d7", "Nxd7"; "knight capture")]
+    #[test_case("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", "a1d1", "Rad1"; "disambiguate by file")]
+    #[test_case("R7/8/8/8/8/8/7k/R3K3 w - - 0 1", "a1a4", "R1a4"; "disambiguate by rank")]
+    #[test_case("4k3/8/8/8/8/Q1Q5/8/Q3K3 w - - 0 1", "a3b2", "Qa3b2"; "disambiguate by square")]
+    #[test_case("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q", "b8=Q+"; "promotion check")]
+    #[test_case("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7b8n", "axb8=N"; "promotion capture")]
+    #[test_case("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", "c4d3", "cxd3+"; "en passant check")]
+    #[test_case("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1", "a1a8", "Ra8#"; "checkmate")]
+    fn test_to_san(fen: &str, uci: &str, expected: &str) {
+        let pos = Position::from_fen(fen).unwrap();
+        let mv = pos.parse_move(uci).unwrap();
+        assert_eq!(pos.to_san(&mv), expected);
+        assert_eq!(pos.parse_move(expected).unwrap(), mv);
+    }
+
+    #[test_case(constants::fen::STARTING_FEN, "Ng1f3", "g1f3"; "over disambiguated")]
+    #[test_case(constants::fen::STARTING_FEN, "e4!?", "e2e4"; "annotation")]
+    #[test_case(constants::fen::TEST_2, "0-0", "e1g1"; "castle with zeros")]
+    #[test_case(constants::fen::TEST_2, "Bxa6", "e2a6"; "capture")]
+    #[test_case(constants::fen::TEST_2, "Ba6", "e2a6"; "missing capture marker")]
+    #[test_case("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b8Q", "b7b8q"; "promotion without equals")]
+    #[test_case("4k3/8/8/8/8/8/8/1R2K1R1 w GB - 0 1", "O-O-O", "e1b1"; "chess960 castle")]
+    fn test_parse_move(fen: &str, s: &str, expected: &str) {
+        let pos = Position::from_fen(fen).unwrap();
+        assert_eq!(pos.parse_move(s).unwrap().to_algebraic(), expected);
+    }
+
+    #[test_case(constants::fen::STARTING_FEN, "Nf4",
+        MoveParseError::Illegal("Nf4".to_string()); "illegal")]
+    #[test_case(constants::fen::STARTING_FEN, "O-O",
+        MoveParseError::Illegal("O-O".to_string()); "illegal castle")]
+    #[test_case(constants::fen::STARTING_FEN, "e9",
+        MoveParseError::Invalid("e9".to_string()); "invalid square")]
+    #[test_case(constants::fen::STARTING_FEN, "",
+        MoveParseError::Invalid("".to_string()); "empty")]
+    #[test_case("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b8",
+        MoveParseError::Illegal("b8".to_string()); "missing promotion")]
+    #[test_case("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", "Rd1",
+        MoveParseError::Ambiguous {
+            san: "Rd1".to_string(),
+            candidates: vec!["a1d1".to_string(), "h1d1".to_string()],
+        }; "ambiguous")]
+    fn test_parse_move_error(fen: &str, s: &str, expected: MoveParseError) {
+        let pos = Position::from_fen(fen).unwrap();
+        assert_eq!(pos.parse_move(s).unwrap_err(), expected);
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Clone, Copy, Debug, PartialEq, Default)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
 pub enum PieceT {
     #[default]
     Any = 0,
@@ -19,7 +19,7 @@ pub const PIECES: [PieceT; 6] = [
     PieceT::King,
 ];
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ColorT {
     White = 0,
     Black,
@@ -27,7 +27,7 @@ pub enum ColorT {
 
 // Bitflags as discriminants
 #[repr(u16)]
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub enum MoveT {
     #[default]
     Quiet = 0x0000,